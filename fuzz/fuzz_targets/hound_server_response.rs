@@ -0,0 +1,29 @@
+#![no_main]
+use houndify::{HoundServerResponse, LazyHoundServerResponse, TemplateData};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let response: HoundServerResponse = match serde_json::from_slice(data) {
+        Ok(response) => response,
+        Err(_) => return,
+    };
+
+    // The accessors and views must not panic on anything that decodes
+    let _ = response.to_string();
+    let _ = format!("{:#}", response);
+    let _ = response.transcription();
+    let _ = response.total_credits();
+    let _ = response.processing_stats();
+    let _ = response.domain_results();
+    for result in response.all_results.iter().flatten() {
+        let _ = TemplateData::from_result(result);
+    }
+
+    let json = serde_json::to_string(&response).unwrap();
+    let decoded: HoundServerResponse = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, response);
+
+    let lazy: LazyHoundServerResponse = serde_json::from_slice(data).unwrap();
+    assert_eq!(lazy.len(), response.all_results.as_ref().map_or(0, Vec::len));
+    assert_eq!(lazy.into_response().unwrap(), response);
+});
@@ -0,0 +1,29 @@
+#![no_main]
+use houndify::RequestInfo;
+use libfuzzer_sys::fuzz_target;
+use serde_json::{Map, Value};
+
+fuzz_target!(|input: (&str, &str)| {
+    let (fields, defaults) = input;
+    let fields: Map<String, Value> = match serde_json::from_str(fields) {
+        Ok(fields) => fields,
+        Err(_) => return,
+    };
+    let defaults: Map<String, Value> = serde_json::from_str(defaults).unwrap_or_default();
+
+    let json = RequestInfo::from(fields.clone()).serialize().unwrap();
+    assert_eq!(serde_json::from_str::<Map<String, Value>>(&json).unwrap(), fields);
+
+    let mut request_info = RequestInfo::from(fields.clone());
+    request_info.apply_defaults(&RequestInfo::from(defaults.clone()));
+    for (k, v) in &fields {
+        if !v.is_object() {
+            assert_eq!(request_info.get(k), Some(v));
+        }
+    }
+    for k in defaults.keys() {
+        assert!(request_info.contains(k));
+    }
+    let json = request_info.serialize().unwrap();
+    serde_json::from_str::<Map<String, Value>>(&json).unwrap();
+});
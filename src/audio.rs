@@ -0,0 +1,444 @@
+use crate::client::Result;
+use crate::error::{HoundifyError, UnsupportedAudioError};
+use crate::query::{RequestInfo, VoiceQuery};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Sample rate of the audio voice queries send when they have to convert it
+pub static TRANSCODE_SAMPLE_RATE: u32 = 16000;
+
+/// Bytes read from the start of a file to detect its format
+const DETECT_LEN: u64 = 64;
+
+/// The audio containers `VoiceQuery::from_file` recognizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    Flac,
+    OggVorbis,
+    Mp3,
+}
+
+impl AudioFormat {
+    /// Detect the format of an audio file from its first bytes
+    pub fn detect(header: &[u8]) -> Option<AudioFormat> {
+        if header.len() >= 12 && &header[..4] == b"RIFF" && &header[8..12] == b"WAVE" {
+            return Some(AudioFormat::Wav);
+        }
+        if header.starts_with(b"fLaC") {
+            return Some(AudioFormat::Flac);
+        }
+        if header.starts_with(b"OggS") {
+            // The first packet of the first page is the codec's identification header, which
+            // starts after the 27 byte page header and its segment table
+            let packet = header.get(26).map(|&segments| 27 + usize::from(segments))?;
+            return match header.get(packet..packet + 7) {
+                Some(b"\x01vorbis") => Some(AudioFormat::OggVorbis),
+                _ => None,
+            };
+        }
+        // An ID3 tag, or the sync word of an MPEG audio layer III frame
+        if header.starts_with(b"ID3")
+            || (header.len() >= 2 && header[0] == 0xff && header[1] & 0xe6 == 0xe2)
+        {
+            return Some(AudioFormat::Mp3);
+        }
+        None
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "WAV",
+            AudioFormat::Flac => "FLAC",
+            AudioFormat::OggVorbis => "Ogg Vorbis",
+            AudioFormat::Mp3 => "MP3",
+        }
+    }
+
+    #[cfg(feature = "transcode")]
+    fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Flac => "flac",
+            AudioFormat::OggVorbis => "ogg",
+            AudioFormat::Mp3 => "mp3",
+        }
+    }
+}
+
+fn unsupported(msg: &str) -> HoundifyError {
+    HoundifyError::new(UnsupportedAudioError::new(msg).into())
+}
+
+fn io_error(e: std::io::Error) -> HoundifyError {
+    HoundifyError::new(e.into())
+}
+
+/// The format of the file at `path`, detected from its first bytes
+fn detect_file(path: &Path) -> Result<AudioFormat> {
+    let mut header = vec![];
+    File::open(path)
+        .and_then(|f| f.take(DETECT_LEN).read_to_end(&mut header))
+        .map_err(io_error)?;
+    AudioFormat::detect(&header).ok_or_else(|| unsupported("unrecognized audio format"))
+}
+
+impl VoiceQuery {
+    /// A voice query reading its audio from a file, whose format is detected from its first
+    /// bytes. WAV files are sent as they are. With the `transcode` feature, FLAC, Ogg Vorbis and
+    /// MP3 files, and WAV files other than 8 or 16 kHz mono 16 bit PCM, are decoded and sent as
+    /// 16 kHz mono 16 bit PCM WAV; without it they fail with an `UnsupportedAudioError`.
+    pub fn from_file<P: AsRef<Path>, U: Into<String>>(
+        path: P,
+        user_id: U,
+        request_info: RequestInfo,
+    ) -> Result<VoiceQuery> {
+        let path = path.as_ref();
+        let audio = open(path, detect_file(path)?)?;
+        Ok(VoiceQuery::new(audio, user_id, request_info))
+    }
+}
+
+#[cfg(not(feature = "transcode"))]
+fn open(path: &Path, format: AudioFormat) -> Result<Box<dyn Read + Send>> {
+    match format {
+        AudioFormat::Wav => Ok(Box::new(File::open(path).map_err(io_error)?)),
+        _ => Err(unsupported(&format!(
+            "{} audio needs the \"transcode\" feature",
+            format.name()
+        ))),
+    }
+}
+
+#[cfg(feature = "transcode")]
+fn open(path: &Path, format: AudioFormat) -> Result<Box<dyn Read + Send>> {
+    use symphonia::core::codecs::CODEC_TYPE_PCM_S16LE;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    let file = File::open(path).map_err(io_error)?;
+    let mut hint = Hint::new();
+    hint.with_extension(format.extension());
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut reader = symphonia::default::get_probe()
+        .format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| HoundifyError::new(e.into()))?
+        .format;
+    let track = reader
+        .default_track()
+        .ok_or_else(|| unsupported("no audio track"))?;
+
+    let params = &track.codec_params;
+    let sends_as_is = format == AudioFormat::Wav
+        && params.codec == CODEC_TYPE_PCM_S16LE
+        && params.channels.map(|c| c.count()) == Some(1)
+        && matches!(params.sample_rate, Some(8000) | Some(16000));
+    if sends_as_is {
+        return Ok(Box::new(File::open(path).map_err(io_error)?));
+    }
+
+    let (samples, sample_rate) = decode(reader.as_mut())?;
+    let samples = resample(&samples, sample_rate, TRANSCODE_SAMPLE_RATE);
+    Ok(Box::new(std::io::Cursor::new(wav(
+        &samples,
+        TRANSCODE_SAMPLE_RATE,
+    ))))
+}
+
+/// Decode the default track to mono samples, and its sample rate
+#[cfg(feature = "transcode")]
+fn decode(reader: &mut dyn symphonia::core::formats::FormatReader) -> Result<(Vec<f32>, u32)> {
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::DecoderOptions;
+    use symphonia::core::errors::Error;
+
+    let error = |e: Error| HoundifyError::new(e.into());
+    let track = reader
+        .default_track()
+        .ok_or_else(|| unsupported("no audio track"))?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(error)?;
+    let mut sample_rate = track.codec_params.sample_rate;
+
+    let mut samples = vec![];
+    loop {
+        let packet = match reader.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(error(e)),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet is skipped, as players do
+            Err(Error::DecodeError(_)) => continue,
+            Err(e) => return Err(error(e)),
+        };
+        let spec = *decoded.spec();
+        sample_rate = Some(spec.rate);
+        let channels = spec.channels.count().max(1);
+        let mut buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buf.copy_interleaved_ref(decoded);
+        samples.extend(
+            buf.samples()
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+    }
+    let sample_rate = sample_rate.ok_or_else(|| unsupported("unknown sample rate"))?;
+    Ok((samples, sample_rate))
+}
+
+/// Resample mono audio
+#[cfg(feature = "transcode")]
+fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    let mut resampler = Resampler::new(from, to);
+    let mut out = vec![];
+    resampler.push(samples, &mut out);
+    resampler.finish(&mut out);
+    out
+}
+
+/// Resamples mono audio as it arrives. Each output sample is the mean of the input samples it
+/// spans when downsampling, which filters out most of what the lower rate cannot carry, and is
+/// linearly interpolated when upsampling.
+#[derive(Debug, Clone)]
+pub(crate) struct Resampler {
+    /// Input samples per output sample
+    step: f64,
+    /// Input samples not yet consumed
+    pending: Vec<f32>,
+    /// Position of the next output sample in `pending`
+    pos: f64,
+}
+
+impl Resampler {
+    pub(crate) fn new(from: u32, to: u32) -> Self {
+        Resampler {
+            step: f64::from(from) / f64::from(to),
+            pending: vec![],
+            pos: 0.0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        if (self.step - 1.0).abs() < f64::EPSILON {
+            out.extend_from_slice(samples);
+            return;
+        }
+        self.pending.extend_from_slice(samples);
+        // Downsampling needs the whole window of an output sample, upsampling the input
+        // sample after it
+        let needed = self.step.max(1.0 + f64::EPSILON);
+        while self.pos + needed <= self.pending.len() as f64 {
+            out.push(self.sample());
+            self.pos += self.step;
+        }
+        let consumed = (self.pos as usize).min(self.pending.len());
+        self.pending.drain(..consumed);
+        self.pos -= consumed as f64;
+    }
+
+    /// Resample what is left at the end of the stream
+    pub(crate) fn finish(&mut self, out: &mut Vec<f32>) {
+        while self.pos < self.pending.len() as f64 {
+            out.push(self.sample());
+            self.pos += self.step;
+        }
+        self.pending.clear();
+        self.pos = 0.0;
+    }
+
+    fn sample(&self) -> f32 {
+        let samples = &self.pending;
+        let first = self.pos as usize;
+        if self.step > 1.0 {
+            let end = ((self.pos + self.step) as usize).clamp(first + 1, samples.len());
+            let window = &samples[first..end];
+            window.iter().sum::<f32>() / window.len() as f32
+        } else {
+            let next = samples.get(first + 1).unwrap_or(&samples[first]);
+            let frac = (self.pos - first as f64) as f32;
+            samples[first] + (next - samples[first]) * frac
+        }
+    }
+}
+
+/// A sample in [-1, 1] as 16 bit PCM
+pub(crate) fn pcm16(sample: f32) -> i16 {
+    (sample * 32768.0).round().clamp(-32768.0, 32767.0) as i16
+}
+
+/// The header of a mono 16 bit PCM WAV file with `data_len` bytes of samples
+pub(crate) fn wav_header(sample_rate: u32, data_len: u32) -> Vec<u8> {
+    let mut wav = Vec::with_capacity(44);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&data_len.saturating_add(36).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // bytes per second
+    wav.extend_from_slice(&2u16.to_le_bytes()); // bytes per frame
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    wav
+}
+
+/// Mono 16 bit PCM WAV
+#[cfg(feature = "transcode")]
+fn wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let mut wav = wav_header(sample_rate, (samples.len() * 2) as u32);
+    for s in samples {
+        wav.extend_from_slice(&pcm16(*s).to_le_bytes());
+    }
+    wav
+}
+
+#[cfg(test)]
+mod audio_tests {
+    use super::*;
+    use crate::query::Query;
+
+    fn body(mut query: VoiceQuery) -> Vec<u8> {
+        let mut body = vec![];
+        match query.body() {
+            Some(crate::query::QueryBody::Reader(mut r)) => r.read_to_end(&mut body).unwrap(),
+            _ => panic!("voice queries send their audio as a reader"),
+        };
+        body
+    }
+
+    #[test]
+    fn test_detect() {
+        let wav = std::fs::read("tests/whattimeisit.wav").unwrap();
+        assert_eq!(AudioFormat::detect(&wav), Some(AudioFormat::Wav));
+        let mp3 = std::fs::read("tests/whattimeisit.mp3").unwrap();
+        assert_eq!(AudioFormat::detect(&mp3), Some(AudioFormat::Mp3));
+        assert_eq!(AudioFormat::detect(b"ID3\x04\x00"), Some(AudioFormat::Mp3));
+        assert_eq!(
+            AudioFormat::detect(b"fLaC\x00\x00\x00\x22"),
+            Some(AudioFormat::Flac)
+        );
+
+        let mut ogg = b"OggS\x00\x02".to_vec();
+        ogg.resize(26, 0);
+        ogg.extend_from_slice(b"\x01\x1e\x01vorbis");
+        assert_eq!(AudioFormat::detect(&ogg), Some(AudioFormat::OggVorbis));
+        ogg.truncate(28);
+        ogg.extend_from_slice(b"OpusHead");
+        assert_eq!(AudioFormat::detect(&ogg), None);
+
+        assert_eq!(AudioFormat::detect(b"{\"Status\": \"OK\"}"), None);
+        assert_eq!(AudioFormat::detect(b""), None);
+    }
+
+    #[test]
+    fn test_resampler_streams() {
+        let input: Vec<f32> = (0..1000).map(|i| (i as f32 / 10.0).sin()).collect();
+        for &(from, to) in &[(44100, 16000), (8000, 16000), (16000, 16000)] {
+            let mut whole = vec![];
+            let mut resampler = Resampler::new(from, to);
+            resampler.push(&input, &mut whole);
+            resampler.finish(&mut whole);
+
+            let mut streamed = vec![];
+            let mut resampler = Resampler::new(from, to);
+            for chunk in input.chunks(37) {
+                resampler.push(chunk, &mut streamed);
+            }
+            resampler.finish(&mut streamed);
+            assert_eq!(streamed.len(), whole.len());
+            for (a, b) in streamed.iter().zip(&whole) {
+                assert!((a - b).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wav_is_sent_as_is() {
+        let query =
+            VoiceQuery::from_file("tests/whattimeisit.wav", "user", RequestInfo::new()).unwrap();
+        assert_eq!(
+            body(query),
+            std::fs::read("tests/whattimeisit.wav").unwrap()
+        );
+
+        let err = VoiceQuery::from_file("Cargo.toml", "user", RequestInfo::new()).unwrap_err();
+        assert!(err.downcast_ref::<UnsupportedAudioError>().is_some());
+    }
+
+    #[cfg(not(feature = "transcode"))]
+    #[test]
+    fn test_mp3_needs_transcode() {
+        let err = VoiceQuery::from_file("tests/whattimeisit.mp3", "user", RequestInfo::new())
+            .unwrap_err();
+        let err = err.downcast_ref::<UnsupportedAudioError>().unwrap();
+        assert!(err.message().contains("transcode"));
+    }
+
+    #[cfg(feature = "transcode")]
+    #[test]
+    fn test_transcode_mp3() {
+        let query =
+            VoiceQuery::from_file("tests/whattimeisit.mp3", "user", RequestInfo::new()).unwrap();
+        let wav = body(query);
+        assert_eq!(AudioFormat::detect(&wav), Some(AudioFormat::Wav));
+        assert_eq!(&wav[22..24], &1u16.to_le_bytes());
+        assert_eq!(&wav[24..28], &TRANSCODE_SAMPLE_RATE.to_le_bytes());
+        // The recordings are of the same phrase, so their lengths roughly agree
+        let original = std::fs::read("tests/whattimeisit.wav").unwrap().len() as f64;
+        let ratio = wav.len() as f64 / original;
+        assert!(ratio > 0.8 && ratio < 1.25, "ratio {}", ratio);
+    }
+
+    #[cfg(feature = "transcode")]
+    #[test]
+    fn test_transcode_stereo_wav() {
+        // One second of 32 kHz stereo, with the left channel at half scale and the right silent
+        let frames: Vec<f32> = (0..64000)
+            .map(|i| if i % 2 == 0 { 0.5 } else { 0.0 })
+            .collect();
+        let mut stereo = wav(&frames, 32000);
+        stereo[22..24].copy_from_slice(&2u16.to_le_bytes());
+        stereo[28..32].copy_from_slice(&(32000u32 * 4).to_le_bytes());
+        stereo[32..34].copy_from_slice(&4u16.to_le_bytes());
+        let path = std::env::temp_dir().join(format!("houndify-stereo-{}.wav", std::process::id()));
+        std::fs::write(&path, stereo).unwrap();
+
+        let query = VoiceQuery::from_file(&path, "user", RequestInfo::new());
+        std::fs::remove_file(&path).unwrap();
+        let mono = body(query.unwrap());
+        assert_eq!(&mono[22..24], &1u16.to_le_bytes());
+        assert_eq!(mono.len(), 44 + 16000 * 2);
+        let sample = i16::from_le_bytes([mono[100], mono[101]]);
+        assert!((i32::from(sample) - 8192).abs() <= 2, "sample {}", sample);
+    }
+
+    #[cfg(feature = "transcode")]
+    #[test]
+    fn test_resample() {
+        let ramp: Vec<f32> = (0..48).map(|i| i as f32 / 48.0).collect();
+        let down = resample(&ramp, 48000, 16000);
+        assert_eq!(down.len(), 16);
+        assert!((down[1] - 4.0 / 48.0).abs() < 1e-6);
+        let up = resample(&ramp, 8000, 16000);
+        assert_eq!(up.len(), 96);
+        assert!((up[3] - 1.5 / 48.0).abs() < 1e-6);
+        assert_eq!(resample(&ramp, 16000, 16000), ramp);
+    }
+}
@@ -0,0 +1,432 @@
+use crate::audio::{pcm16, wav_header, Resampler, TRANSCODE_SAMPLE_RATE};
+use crate::client::Result;
+use crate::error::{HoundifyError, UnsupportedAudioError};
+use crate::query::{RequestInfo, VoiceQuery};
+use std::io::{self, Read};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+
+/// Frames of this length are read from sources which are not already framed
+const FRAME_MS: u32 = 20;
+
+/// The sample rate and channel count of an `AudioSource`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioSpec {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl AudioSpec {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        AudioSpec {
+            sample_rate,
+            channels,
+        }
+    }
+
+    /// Samples in a frame of `FRAME_MS`, across all channels
+    fn frame_len(&self) -> usize {
+        ((self.sample_rate * FRAME_MS / 1000) as usize * usize::from(self.channels)).max(1)
+    }
+}
+
+/// Interleaved PCM samples. `F32` samples range from -1 to 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioFrame {
+    I16(Vec<i16>),
+    F32(Vec<f32>),
+}
+
+impl AudioFrame {
+    /// Number of samples, across all channels
+    pub fn len(&self) -> usize {
+        match self {
+            AudioFrame::I16(samples) => samples.len(),
+            AudioFrame::F32(samples) => samples.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn to_f32(&self) -> Vec<f32> {
+        match self {
+            AudioFrame::I16(samples) => samples.iter().map(|&s| f32::from(s) / 32768.0).collect(),
+            AudioFrame::F32(samples) => samples.clone(),
+        }
+    }
+}
+
+impl From<Vec<i16>> for AudioFrame {
+    fn from(samples: Vec<i16>) -> Self {
+        AudioFrame::I16(samples)
+    }
+}
+
+impl From<Vec<f32>> for AudioFrame {
+    fn from(samples: Vec<f32>) -> Self {
+        AudioFrame::F32(samples)
+    }
+}
+
+/// Live or recorded audio, as frames of PCM samples. Implement it for a capture device to send
+/// what it records with `VoiceQuery::from_source`.
+pub trait AudioSource: Send {
+    fn spec(&self) -> AudioSpec;
+
+    /// The next frame, blocking until it is available, or `None` at the end of the audio
+    fn next_frame(&mut self) -> io::Result<Option<AudioFrame>>;
+}
+
+impl<S: AudioSource + ?Sized> AudioSource for Box<S> {
+    fn spec(&self) -> AudioSpec {
+        (**self).spec()
+    }
+
+    fn next_frame(&mut self) -> io::Result<Option<AudioFrame>> {
+        (**self).next_frame()
+    }
+}
+
+/// Raw little endian 16 bit PCM from a reader. Reading fails with `UnexpectedEof` if the
+/// reader ends in the middle of a sample.
+#[derive(Debug)]
+pub struct ReadSource<R> {
+    reader: R,
+    spec: AudioSpec,
+    frame_len: usize,
+}
+
+impl<R: Read + Send> ReadSource<R> {
+    pub fn new(reader: R, spec: AudioSpec) -> Self {
+        ReadSource {
+            reader,
+            spec,
+            frame_len: spec.frame_len(),
+        }
+    }
+
+    /// Samples per frame, across all channels. Defaults to 20 ms of audio.
+    pub fn frame_len(mut self, samples: usize) -> Self {
+        self.frame_len = samples.max(1);
+        self
+    }
+}
+
+impl<R: Read + Send> AudioSource for ReadSource<R> {
+    fn spec(&self) -> AudioSpec {
+        self.spec
+    }
+
+    fn next_frame(&mut self) -> io::Result<Option<AudioFrame>> {
+        let mut bytes = vec![];
+        (&mut self.reader)
+            .take(self.frame_len as u64 * 2)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() % 2 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "audio ends in the middle of a 16 bit sample",
+            ));
+        }
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        let samples = bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        Ok(Some(AudioFrame::I16(samples)))
+    }
+}
+
+/// A sample type `IterSource` accepts
+pub trait Sample: Copy + Send {
+    fn frame(samples: Vec<Self>) -> AudioFrame;
+}
+
+impl Sample for i16 {
+    fn frame(samples: Vec<Self>) -> AudioFrame {
+        AudioFrame::I16(samples)
+    }
+}
+
+impl Sample for f32 {
+    fn frame(samples: Vec<Self>) -> AudioFrame {
+        AudioFrame::F32(samples)
+    }
+}
+
+/// Interleaved `i16` or `f32` samples from an iterator
+#[derive(Debug)]
+pub struct IterSource<I> {
+    iter: I,
+    spec: AudioSpec,
+    frame_len: usize,
+}
+
+impl<I: Iterator + Send> IterSource<I>
+where
+    I::Item: Sample,
+{
+    pub fn new<T: IntoIterator<IntoIter = I>>(samples: T, spec: AudioSpec) -> Self {
+        IterSource {
+            iter: samples.into_iter(),
+            spec,
+            frame_len: spec.frame_len(),
+        }
+    }
+
+    /// Samples per frame, across all channels. Defaults to 20 ms of audio.
+    pub fn frame_len(mut self, samples: usize) -> Self {
+        self.frame_len = samples.max(1);
+        self
+    }
+}
+
+impl<I: Iterator + Send> AudioSource for IterSource<I>
+where
+    I::Item: Sample,
+{
+    fn spec(&self) -> AudioSpec {
+        self.spec
+    }
+
+    fn next_frame(&mut self) -> io::Result<Option<AudioFrame>> {
+        let samples: Vec<I::Item> = self.iter.by_ref().take(self.frame_len).collect();
+        if samples.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(I::Item::frame(samples)))
+    }
+}
+
+/// Frames sent from another thread, such as a capture callback. The audio ends when every
+/// sender is dropped.
+#[derive(Debug)]
+pub struct ChannelSource {
+    receiver: Receiver<AudioFrame>,
+    spec: AudioSpec,
+}
+
+impl ChannelSource {
+    pub fn new(receiver: Receiver<AudioFrame>, spec: AudioSpec) -> Self {
+        ChannelSource { receiver, spec }
+    }
+}
+
+impl AudioSource for ChannelSource {
+    fn spec(&self) -> AudioSpec {
+        self.spec
+    }
+
+    fn next_frame(&mut self) -> io::Result<Option<AudioFrame>> {
+        Ok(self.receiver.recv().ok())
+    }
+}
+
+/// A channel holding at most `bound` frames, so that a capture thread blocks in `send` while a
+/// voice query is behind sending what it already captured
+pub fn audio_channel(spec: AudioSpec, bound: usize) -> (SyncSender<AudioFrame>, ChannelSource) {
+    let (sender, receiver) = sync_channel(bound);
+    (sender, ChannelSource::new(receiver, spec))
+}
+
+/// Reads an `AudioSource` as a mono 16 bit PCM WAV stream. Audio at rates other than 8 or
+/// 16 kHz is resampled to `TRANSCODE_SAMPLE_RATE`.
+struct SourceReader<S> {
+    source: S,
+    channels: usize,
+    /// Samples at the end of the last frame which do not cover every channel, mixed down
+    /// together with the next frame
+    leftover: Vec<f32>,
+    resampler: Resampler,
+    buf: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<S: AudioSource> SourceReader<S> {
+    fn new(source: S) -> Self {
+        let spec = source.spec();
+        let sample_rate = match spec.sample_rate {
+            8000 | 16000 => spec.sample_rate,
+            _ => TRANSCODE_SAMPLE_RATE,
+        };
+        SourceReader {
+            source,
+            channels: usize::from(spec.channels.max(1)),
+            leftover: vec![],
+            resampler: Resampler::new(spec.sample_rate, sample_rate),
+            // The length of live audio is not known, so the header gives the largest one
+            buf: wav_header(sample_rate, u32::MAX),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    /// `frame` mixed down to one channel, after the samples left over from the last frame
+    fn mono(&mut self, frame: &AudioFrame) -> Vec<f32> {
+        let mut samples = std::mem::take(&mut self.leftover);
+        samples.extend(frame.to_f32());
+        if self.channels == 1 {
+            return samples;
+        }
+        let whole = samples.len() - samples.len() % self.channels;
+        self.leftover = samples.split_off(whole);
+        samples
+            .chunks_exact(self.channels)
+            .map(|frame| frame.iter().sum::<f32>() / self.channels as f32)
+            .collect()
+    }
+}
+
+impl<S: AudioSource> Read for SourceReader<S> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            if self.done {
+                return Ok(0);
+            }
+            let mut samples = vec![];
+            match self.source.next_frame()? {
+                Some(frame) => {
+                    let mono = self.mono(&frame);
+                    self.resampler.push(&mono, &mut samples);
+                }
+                None => {
+                    self.resampler.finish(&mut samples);
+                    self.done = true;
+                }
+            }
+            self.buf.clear();
+            self.pos = 0;
+            for s in samples {
+                self.buf.extend_from_slice(&pcm16(s).to_le_bytes());
+            }
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl VoiceQuery {
+    /// A voice query streaming its audio from `source` as it is captured, sent as mono 16 bit
+    /// PCM WAV. Fails with an `UnsupportedAudioError` if the source has no channels or a sample
+    /// rate of 0.
+    pub fn from_source<S, U>(source: S, user_id: U, request_info: RequestInfo) -> Result<VoiceQuery>
+    where
+        S: AudioSource + 'static,
+        U: Into<String>,
+    {
+        let spec = source.spec();
+        if spec.channels == 0 || spec.sample_rate == 0 {
+            let msg = format!("invalid audio spec {:?}", spec);
+            return Err(HoundifyError::new(UnsupportedAudioError::new(&msg).into()));
+        }
+        let reader = SourceReader::new(source);
+        Ok(VoiceQuery::new(Box::new(reader), user_id, request_info))
+    }
+}
+
+#[cfg(test)]
+mod audio_source_tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::query::{Query, QueryBody};
+    use crate::test_server;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn body(mut query: VoiceQuery) -> Vec<u8> {
+        let mut body = vec![];
+        match query.body() {
+            Some(QueryBody::Reader(mut r)) => r.read_to_end(&mut body).unwrap(),
+            _ => panic!("voice queries send their audio as a reader"),
+        };
+        body
+    }
+
+    fn samples(wav: &[u8]) -> Vec<i16> {
+        wav[44..]
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn test_read_source() {
+        let pcm: Vec<u8> = (0..1000i16)
+            .flat_map(|s| s.to_le_bytes().to_vec())
+            .collect();
+        let mut source = ReadSource::new(Cursor::new(pcm.clone()), AudioSpec::new(16000, 1));
+        assert_eq!(source.next_frame().unwrap().unwrap().len(), 320);
+
+        let source = ReadSource::new(Cursor::new(pcm), AudioSpec::new(16000, 1)).frame_len(7);
+        let query = VoiceQuery::from_source(source, "user", RequestInfo::new()).unwrap();
+        let wav = body(query);
+        assert_eq!(&wav[24..28], &16000u32.to_le_bytes());
+        assert_eq!(samples(&wav), (0..1000).collect::<Vec<i16>>());
+
+        let mut source = ReadSource::new(Cursor::new(vec![1, 0, 2]), AudioSpec::new(16000, 1));
+        let err = source.next_frame().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_downmix_across_frames() {
+        // Frames of 3 samples split the stereo pairs, which are carried into the next frame
+        let stereo = (0..12i16).map(|i| if i % 2 == 0 { 1000 } else { 3000 });
+        let source = IterSource::new(stereo, AudioSpec::new(16000, 2)).frame_len(3);
+        let wav = body(VoiceQuery::from_source(source, "user", RequestInfo::new()).unwrap());
+        assert_eq!(samples(&wav), vec![2000; 6]);
+    }
+
+    #[test]
+    fn test_iter_source_downmixes_and_resamples() {
+        // 48 kHz stereo, left at half scale and right silent
+        let stereo = (0..9600).map(|i| if i % 2 == 0 { 0.5f32 } else { 0.0 });
+        let source = IterSource::new(stereo, AudioSpec::new(48000, 2));
+        let wav = body(VoiceQuery::from_source(source, "user", RequestInfo::new()).unwrap());
+        assert_eq!(&wav[24..28], &TRANSCODE_SAMPLE_RATE.to_le_bytes());
+        let samples = samples(&wav);
+        assert_eq!(samples.len(), 1600);
+        assert!(samples.iter().all(|&s| s == 8192));
+
+        let source = IterSource::new(vec![0i16; 10], AudioSpec::new(8000, 0));
+        assert!(VoiceQuery::from_source(source, "user", RequestInfo::new()).is_err());
+    }
+
+    #[test]
+    fn test_channel_source_streams_into_query() {
+        let url = test_server::serve(|request| {
+            assert_eq!(&request.body[..4], b"RIFF");
+            assert_eq!(samples(&request.body).len(), 50 * 160);
+            test_server::ok_response("what time is it", "Clock", 1.0)
+        });
+        let client = Client::new(&url, "client", "a2V5", None);
+
+        let (sender, source) = audio_channel(AudioSpec::new(8000, 1), 2);
+        let sent = Arc::new(AtomicUsize::new(0));
+        let capture = {
+            let sent = sent.clone();
+            thread::spawn(move || {
+                for _ in 0..50 {
+                    sender.send(AudioFrame::I16(vec![100; 160])).unwrap();
+                    sent.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        };
+        // Nothing reads the channel until the query starts, so the capture thread waits
+        thread::sleep(std::time::Duration::from_millis(50));
+        assert!(sent.load(Ordering::SeqCst) <= 2);
+
+        let query = VoiceQuery::from_source(source, "user", RequestInfo::new()).unwrap();
+        let response = client.voice_query(query).unwrap();
+        capture.join().unwrap();
+        assert_eq!(response.written_response(), Some("what time is it"));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::client::{Client, Result};
+use crate::query::TextQuery;
+use crate::response::HoundServerResponse;
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The result of a single query of a batch
+#[derive(Debug)]
+pub struct BatchOutcome {
+    /// Position of the query in the input
+    pub index: usize,
+    pub query: String,
+    pub latency: Duration,
+    pub result: Result<HoundServerResponse>,
+}
+
+/// Reported after each query of a batch completes
+#[derive(Debug)]
+pub struct BatchProgress<'a> {
+    /// Number of queries completed so far, including this one
+    pub completed: usize,
+    pub outcome: &'a BatchOutcome,
+}
+
+/// Summary of a batch run. Outcomes are in the same order as the input queries.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    pub outcomes: Vec<BatchOutcome>,
+
+    /// Wall clock time taken by the whole batch
+    pub elapsed: Duration,
+
+    /// Credits used, keyed on domain name
+    pub credits_by_domain: HashMap<String, f64>,
+}
+
+impl BatchReport {
+    pub fn succeeded(&self) -> usize {
+        self.outcomes.iter().filter(|o| o.result.is_ok()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.outcomes.len() - self.succeeded()
+    }
+
+    pub fn total_credits(&self) -> f64 {
+        self.credits_by_domain.values().sum()
+    }
+
+    pub fn mean_latency(&self) -> Option<Duration> {
+        if self.outcomes.is_empty() {
+            return None;
+        }
+        let total: Duration = self.outcomes.iter().map(|o| o.latency).sum();
+        Some(total / self.outcomes.len() as u32)
+    }
+
+    /// The latency below which `p` percent of the queries completed
+    pub fn latency_percentile(&self, p: f64) -> Option<Duration> {
+        let mut latencies: Vec<Duration> = self.outcomes.iter().map(|o| o.latency).collect();
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort();
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * (latencies.len() - 1) as f64).round();
+        Some(latencies[rank as usize])
+    }
+}
+
+/// Runs text queries through a client on a bounded number of threads
+#[derive(Debug)]
+pub struct BatchRunner<'c> {
+    client: &'c Client,
+    concurrency: usize,
+}
+
+impl<'c> BatchRunner<'c> {
+    /// Create a runner sending at most `concurrency` queries at a time
+    pub fn new(client: &'c Client, concurrency: usize) -> Self {
+        BatchRunner {
+            client,
+            concurrency: concurrency.max(1),
+        }
+    }
+
+    pub fn run<I>(&self, queries: I) -> BatchReport
+    where
+        I: IntoIterator<Item = TextQuery>,
+        I::IntoIter: Send,
+    {
+        self.run_with_progress(queries, |_| {})
+    }
+
+    /// Like `run`, calling `on_progress` on the calling thread as each query completes
+    pub fn run_with_progress<I, F>(&self, queries: I, mut on_progress: F) -> BatchReport
+    where
+        I: IntoIterator<Item = TextQuery>,
+        I::IntoIter: Send,
+        F: FnMut(&BatchProgress),
+    {
+        let start = Instant::now();
+        let queries = Mutex::new(queries.into_iter().enumerate());
+        let (sender, receiver) = mpsc::channel();
+        let mut outcomes = vec![];
+
+        thread::scope(|scope| {
+            for _ in 0..self.concurrency {
+                let sender = sender.clone();
+                let queries = &queries;
+                scope.spawn(move || loop {
+                    let next = queries.lock().unwrap().next();
+                    let (index, query) = match next {
+                        Some(q) => q,
+                        None => break,
+                    };
+                    let started = Instant::now();
+                    let result = self.client.text_query(&query);
+                    let outcome = BatchOutcome {
+                        index,
+                        query: query.query,
+                        latency: started.elapsed(),
+                        result,
+                    };
+                    if sender.send(outcome).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+
+            for outcome in receiver {
+                outcomes.push(outcome);
+                on_progress(&BatchProgress {
+                    completed: outcomes.len(),
+                    outcome: outcomes.last().unwrap(),
+                });
+            }
+        });
+
+        outcomes.sort_by_key(|o| o.index);
+        let mut credits_by_domain = HashMap::new();
+        for response in outcomes.iter().filter_map(|o| o.result.as_ref().ok()) {
+            for usage in response.domain_usage.iter().flatten() {
+                *credits_by_domain.entry(usage.domain.clone()).or_insert(0.0) += usage.credits_used;
+            }
+        }
+        BatchReport {
+            outcomes,
+            elapsed: start.elapsed(),
+            credits_by_domain,
+        }
+    }
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use super::*;
+    use crate::query::RequestInfo;
+    use crate::test_server;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_run_preserves_order_and_bounds_concurrency() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let (current, max) = (in_flight.clone(), max_in_flight.clone());
+        let url = test_server::serve(move |request| {
+            assert_eq!(request.method, "GET");
+            assert!(request.headers.contains_key("hound-client-authentication"));
+            assert!(request.body.is_empty());
+            let n = current.fetch_add(1, Ordering::SeqCst) + 1;
+            max.fetch_max(n, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            current.fetch_sub(1, Ordering::SeqCst);
+            let query = request.path.split("query=").nth(1).unwrap_or("");
+            test_server::ok_response(query, "Arithmetic", 0.5)
+        });
+        let client = Client::new(&url, "client", "a2V5", None);
+
+        let texts: Vec<String> = (0..8).map(|i| format!("query{}", i)).collect();
+        let queries = texts
+            .iter()
+            .map(|t| TextQuery::new(t, "user", RequestInfo::new()));
+        let mut progress = vec![];
+        let report =
+            BatchRunner::new(&client, 3).run_with_progress(queries, |p| progress.push(p.completed));
+
+        assert_eq!(progress, (1..=8).collect::<Vec<_>>());
+        assert!(max_in_flight.load(Ordering::SeqCst) <= 3);
+        assert_eq!(report.succeeded(), 8);
+        assert_eq!(report.total_credits(), 4.0);
+        for (i, outcome) in report.outcomes.iter().enumerate() {
+            assert_eq!(outcome.index, i);
+            let response = outcome.result.as_ref().unwrap();
+            let result = &response.all_results.as_ref().unwrap()[0];
+            assert_eq!(result["WrittenResponse"], format!("query{}", i));
+        }
+        assert!(report.latency_percentile(100.0).unwrap() >= report.mean_latency().unwrap());
+    }
+}
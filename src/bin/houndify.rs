@@ -0,0 +1,116 @@
+use houndify::harness::{Cassette, TestSuite};
+use houndify::Client;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+static USAGE: &str = "Usage: houndify test <SUITE> [--junit <FILE>] [--cassette <FILE> [--record]]
+
+Run the regression test suite <SUITE> (.json, .yaml or .yml).
+
+Options:
+    --junit <FILE>     Write a JUnit XML report to <FILE>
+    --cassette <FILE>  Replay the responses recorded in <FILE> instead of querying Houndify
+    --record           Query Houndify and record the responses into the cassette
+
+Queries are sent with the credentials in HOUNDIFY_CLIENT_ID and HOUNDIFY_CLIENT_KEY, to
+HOUNDIFY_API_URL if set.";
+
+struct Args {
+    suite: PathBuf,
+    junit: Option<PathBuf>,
+    cassette: Option<PathBuf>,
+    record: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Option<Args> {
+    if args.next()? != "test" {
+        return None;
+    }
+    let mut suite = None;
+    let mut junit = None;
+    let mut cassette = None;
+    let mut record = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--junit" => junit = Some(PathBuf::from(args.next()?)),
+            "--cassette" => cassette = Some(PathBuf::from(args.next()?)),
+            "--record" => record = true,
+            _ if suite.is_none() && !arg.starts_with("--") => suite = Some(PathBuf::from(arg)),
+            _ => return None,
+        }
+    }
+    if record && cassette.is_none() {
+        return None;
+    }
+    Some(Args {
+        suite: suite?,
+        junit,
+        cassette,
+        record,
+    })
+}
+
+fn client() -> Client {
+    match Client::from_env() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    }
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Some(args) => args,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let suite = match TestSuite::load(&args.suite) {
+        Ok(suite) => suite,
+        Err(e) => {
+            eprintln!("Cannot load {}: {}", args.suite.display(), e);
+            process::exit(2);
+        }
+    };
+
+    let report = match &args.cassette {
+        Some(path) if args.record => {
+            let mut cassette = Cassette::default();
+            let report = suite.record(&client(), &mut cassette);
+            if let Err(e) = cassette.save(path) {
+                eprintln!("Cannot save {}: {}", path.display(), e);
+                process::exit(2);
+            }
+            report
+        }
+        Some(path) => match Cassette::load(path) {
+            Ok(cassette) => suite.replay(&cassette),
+            Err(e) => {
+                eprintln!("Cannot load {}: {}", path.display(), e);
+                process::exit(2);
+            }
+        },
+        None => suite.run(&client()),
+    };
+
+    if let Some(path) = &args.junit {
+        if let Err(e) = std::fs::write(path, report.junit_xml()) {
+            eprintln!("Cannot write {}: {}", path.display(), e);
+            process::exit(2);
+        }
+    }
+    print!("{}", report.failure_diff());
+    println!(
+        "{}: {} passed, {} failed",
+        report.name,
+        report.cases.len() - report.failed(),
+        report.failed()
+    );
+    if !report.passed() {
+        process::exit(1);
+    }
+}
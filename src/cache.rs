@@ -0,0 +1,326 @@
+use crate::query::RequestInfo;
+use crate::response::HoundServerResponse;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// RequestInfo fields that are part of the cache key unless configured otherwise
+pub static DEFAULT_KEY_FIELDS: &[&str] = &[
+    "InputLanguageIETFTag",
+    "OutputLanguageIETFTag",
+    "UnitPreference",
+    "Latitude",
+    "Longitude",
+    "ConversationState",
+];
+
+/// Command and nugget kinds whose answers change over time and are never cached unless
+/// configured otherwise
+pub static DEFAULT_UNCACHED_KINDS: &[&str] = &["DateAndTime", "Weather", "WeatherCommand"];
+
+#[derive(Debug)]
+struct Entry {
+    inserted: Instant,
+    response: HoundServerResponse,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    order: VecDeque<String>,
+}
+
+/// Cache for the responses to text queries.
+///
+/// Entries are keyed on the normalized query text, the user ID and a configurable set of fields
+/// of the RequestInfo as sent, after the client's defaults and location are merged in. Only
+/// successful responses are cached, and never those containing a result whose "CommandKind",
+/// or the "NuggetKind" of one of its information nuggets, is excluded. Responses that ran
+/// client actions are not cached either, so that the action is performed for every query.
+#[derive(Debug)]
+pub struct ResponseCache {
+    ttl: Duration,
+    max_entries: usize,
+    key_fields: Vec<String>,
+    uncached_kinds: HashSet<String>,
+    entries: Mutex<Entries>,
+}
+
+impl ResponseCache {
+    /// Create a cache holding up to `max_entries` responses for `ttl` each
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        ResponseCache {
+            ttl,
+            max_entries,
+            key_fields: DEFAULT_KEY_FIELDS.iter().map(|f| f.to_string()).collect(),
+            uncached_kinds: DEFAULT_UNCACHED_KINDS
+                .iter()
+                .map(|k| k.to_string())
+                .collect(),
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Set the RequestInfo fields that are part of the cache key
+    pub fn key_fields(mut self, fields: &[&str]) -> Self {
+        self.key_fields = fields.iter().map(|f| f.to_string()).collect();
+        self
+    }
+
+    /// Never cache responses with results of this "CommandKind" or "NuggetKind"
+    pub fn never_cache(mut self, kind: &str) -> Self {
+        self.uncached_kinds.insert(kind.to_string());
+        self
+    }
+
+    /// Allow caching responses with results of this "CommandKind" or "NuggetKind"
+    pub fn allow_cache(mut self, kind: &str) -> Self {
+        self.uncached_kinds.remove(kind);
+        self
+    }
+
+    pub fn get(
+        &self,
+        query: &str,
+        user_id: &str,
+        request_info: &RequestInfo,
+    ) -> Option<HoundServerResponse> {
+        self.get_at(Instant::now(), query, user_id, request_info)
+    }
+
+    /// Cache `response` if it is cacheable
+    pub fn insert(
+        &self,
+        query: &str,
+        user_id: &str,
+        request_info: &RequestInfo,
+        response: &HoundServerResponse,
+    ) {
+        self.insert_at(Instant::now(), query, user_id, request_info, response)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.map.clear();
+        entries.order.clear();
+    }
+
+    fn get_at(
+        &self,
+        now: Instant,
+        query: &str,
+        user_id: &str,
+        request_info: &RequestInfo,
+    ) -> Option<HoundServerResponse> {
+        let key = self.key(query, user_id, request_info);
+        let entries = self.entries.lock().unwrap();
+        entries
+            .map
+            .get(&key)
+            .filter(|e| now.duration_since(e.inserted) < self.ttl)
+            .map(|e| e.response.clone())
+    }
+
+    fn insert_at(
+        &self,
+        now: Instant,
+        query: &str,
+        user_id: &str,
+        request_info: &RequestInfo,
+        response: &HoundServerResponse,
+    ) {
+        if self.max_entries == 0 || !self.is_cacheable(response) {
+            return;
+        }
+        let key = self.key(query, user_id, request_info);
+        let mut entries = self.entries.lock().unwrap();
+        let ttl = self.ttl;
+        let Entries { map, order } = &mut *entries;
+
+        // Drop expired entries and any previous entry for this key, then the oldest entries
+        map.retain(|k, e| k != &key && now.duration_since(e.inserted) < ttl);
+        order.retain(|k| map.contains_key(k));
+        while map.len() >= self.max_entries {
+            match order.pop_front() {
+                Some(oldest) => map.remove(&oldest),
+                None => break,
+            };
+        }
+        order.push_back(key.clone());
+        map.insert(
+            key,
+            Entry {
+                inserted: now,
+                response: response.clone(),
+            },
+        );
+    }
+
+    fn is_cacheable(&self, response: &HoundServerResponse) -> bool {
+        if !response.is_ok() || !response.client_action_outcomes.is_empty() {
+            return false;
+        }
+        let results = match &response.all_results {
+            Some(results) => results,
+            None => return false,
+        };
+        !results
+            .iter()
+            .any(|result| result_kinds(result).any(|kind| self.uncached_kinds.contains(kind)))
+    }
+
+    fn key(&self, query: &str, user_id: &str, request_info: &RequestInfo) -> String {
+        let mut fields = Map::new();
+        for field in &self.key_fields {
+            if let Some(v) = request_info.get(field) {
+                fields.insert(field.clone(), v.clone());
+            }
+        }
+        format!(
+            "{}\n{}\n{}",
+            normalize(query),
+            user_id,
+            Value::Object(fields)
+        )
+    }
+}
+
+/// The "CommandKind" of a result and the "NuggetKind" of each of its information nuggets
+fn result_kinds<'a>(result: &'a Map<String, Value>) -> impl Iterator<Item = &'a str> + 'a {
+    let nugget_kinds = result
+        .get("InformationNuggets")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|n| n.get("NuggetKind").and_then(Value::as_str));
+    result
+        .get("CommandKind")
+        .and_then(Value::as_str)
+        .into_iter()
+        .chain(nugget_kinds)
+}
+
+/// Lowercase, collapse whitespace and drop trailing punctuation
+fn normalize(query: &str) -> String {
+    query
+        .trim_end_matches(|c: char| c.is_whitespace() || ['?', '!', '.'].contains(&c))
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+    use crate::client_action::ClientActionOutcome;
+    use serde_json::json;
+
+    fn response(command_kind: &str) -> HoundServerResponse {
+        serde_json::from_value(json!({
+            "Format": "SoundHoundVoiceSearchResult",
+            "FormatVersion": "1.0",
+            "Status": "OK",
+            "QueryID": "deadbeef",
+            "AllResults": [{"CommandKind": command_kind, "WrittenResponse": "2"}],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_normalized_hit() {
+        let cache = ResponseCache::new(Duration::from_secs(60), 10);
+        let request_info = RequestInfo::new();
+        cache.insert(
+            "What is one plus one?",
+            "user",
+            &request_info,
+            &response("InformationCommand"),
+        );
+        assert!(cache
+            .get("what  is one plus one", "user", &request_info)
+            .is_some());
+        assert!(cache
+            .get("what is one plus one ?", "user", &request_info)
+            .is_some());
+        assert!(cache
+            .get("what is one plus one?! ", "user", &request_info)
+            .is_some());
+        assert!(cache
+            .get("what is one plus two", "user", &request_info)
+            .is_none());
+        assert!(cache
+            .get("what is one plus one", "someone-else", &request_info)
+            .is_none());
+    }
+
+    #[test]
+    fn test_key_fields() {
+        let cache = ResponseCache::new(Duration::from_secs(60), 10);
+        let mut english = RequestInfo::new();
+        english.input_language_ietf_tag("en");
+        let mut french = RequestInfo::new();
+        french.input_language_ietf_tag("fr");
+        let answer = response("InformationCommand");
+        cache.insert("one plus one", "user", &english, &answer);
+        assert!(cache.get("one plus one", "user", &english).is_some());
+        assert!(cache.get("one plus one", "user", &french).is_none());
+    }
+
+    #[test]
+    fn test_ttl_and_size() {
+        let start = Instant::now();
+        let cache = ResponseCache::new(Duration::from_secs(60), 2);
+        let request_info = RequestInfo::new();
+        let answer = response("InformationCommand");
+        cache.insert_at(start, "a", "user", &request_info, &answer);
+        cache.insert_at(start, "b", "user", &request_info, &answer);
+        cache.insert_at(start, "c", "user", &request_info, &answer);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get_at(start, "a", "user", &request_info).is_none());
+        assert!(cache.get_at(start, "c", "user", &request_info).is_some());
+        let later = start + Duration::from_secs(60);
+        assert!(cache.get_at(later, "c", "user", &request_info).is_none());
+    }
+
+    #[test]
+    fn test_client_actions_are_not_cached() {
+        let cache = ResponseCache::new(Duration::from_secs(60), 10);
+        let request_info = RequestInfo::new();
+        let mut answer = response("AlarmCommand");
+        answer.client_action_outcomes = vec![ClientActionOutcome {
+            succeeded: true,
+            result: Map::new(),
+        }];
+        cache.insert("set an alarm", "user", &request_info, &answer);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_uncached_kinds() {
+        let cache = ResponseCache::new(Duration::from_secs(60), 10).never_cache("MusicCommand");
+        let request_info = RequestInfo::new();
+        cache.insert(
+            "weather",
+            "user",
+            &request_info,
+            &response("WeatherCommand"),
+        );
+        cache.insert("play", "user", &request_info, &response("MusicCommand"));
+        assert!(cache.is_empty());
+
+        let file = std::fs::File::open("tests/sample_result.json").unwrap();
+        let time: HoundServerResponse = serde_json::from_reader(file).unwrap();
+        cache.insert("what time is it in toronto", "user", &request_info, &time);
+        assert!(cache.is_empty());
+    }
+}
@@ -1,11 +1,25 @@
+use crate::cache::ResponseCache;
+use crate::client_action::ClientActions;
+use crate::compression::{self, CompressionCounters, CompressionStats};
 use crate::error::HoundifyError;
-use crate::query::{Query, RequestInfo, TextQuery, VoiceQuery};
-use crate::response::HoundServerResponse;
-use base64;
+use crate::format::FormatVersionPolicy;
+use crate::location::LocationProvider;
+use crate::metadata::{ClientMetadata, SDK};
+use crate::query::{Query, QueryBody, RequestInfo, TextQuery, VoiceQuery};
+use crate::rate_limit::RateLimiter;
+use crate::response::{HoundServerResponse, LazyHoundServerResponse};
+use crate::usage::{UsageSnapshot, UsageTracker};
 use hmac::{Hmac, Mac};
-use reqwest::blocking::{Body, Client as HttpClient};
-use reqwest::header::HeaderMap;
+use reqwest::blocking::{Body, Client as HttpClient, Response as HttpResponse};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING};
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
 use sha2::Sha256;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::io::{BufReader, Cursor, Read};
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 use uuid::Uuid;
 
@@ -21,6 +35,29 @@ fn get_current_timestamp() -> u64 {
         .as_secs()
 }
 
+/// Longest RequestInfo sent in a header by `RequestInfoPlacement::default()`. Proxies commonly
+/// limit all the headers of a request to 8KB.
+pub static DEFAULT_MAX_REQUEST_INFO_HEADER_LEN: usize = 4096;
+
+/// Where the RequestInfo of a query is sent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestInfoPlacement {
+    /// In the "Houndify-Request-Info" header
+    Header,
+    /// At the start of the request body, its length given by "Houndify-Request-Info-Length".
+    /// Text queries are sent with POST instead of GET.
+    Body,
+    /// In the header, unless its JSON is longer than this many bytes or is not a valid header
+    /// value
+    Auto(usize),
+}
+
+impl Default for RequestInfoPlacement {
+    fn default() -> Self {
+        RequestInfoPlacement::Auto(DEFAULT_MAX_REQUEST_INFO_HEADER_LEN)
+    }
+}
+
 #[derive(Debug)]
 pub struct Client {
     api_url: String,
@@ -28,6 +65,20 @@ pub struct Client {
     client_key: String,
     http_client: HttpClient,
     request_id_generator: fn() -> String,
+    client_actions: ClientActions,
+    conversation_states: Mutex<HashMap<String, Value>>,
+    location_provider: Option<Box<dyn LocationProvider>>,
+    usage_tracker: Option<Arc<UsageTracker>>,
+    rate_limiter: Option<RateLimiter>,
+    response_cache: Option<ResponseCache>,
+    default_request_info: RequestInfo,
+    metadata: ClientMetadata,
+    request_info_placement: RequestInfoPlacement,
+    response_compression: bool,
+    request_compression_threshold: Option<usize>,
+    compression: Arc<CompressionCounters>,
+    keep_raw_response: bool,
+    format_version_policy: FormatVersionPolicy,
 }
 
 impl Client {
@@ -53,6 +104,188 @@ impl Client {
             client_key: client_key.to_string(),
             http_client,
             request_id_generator,
+            client_actions: ClientActions::new(),
+            conversation_states: Mutex::new(HashMap::new()),
+            location_provider: None,
+            usage_tracker: None,
+            rate_limiter: None,
+            response_cache: None,
+            default_request_info: RequestInfo::new(),
+            metadata: ClientMetadata::new(),
+            request_info_placement: RequestInfoPlacement::default(),
+            response_compression: true,
+            request_compression_threshold: None,
+            compression: Arc::new(CompressionCounters::default()),
+            keep_raw_response: false,
+            format_version_policy: FormatVersionPolicy::default(),
+        }
+    }
+
+    /// Register a handler for results of the given "CommandKind" that require a client side
+    /// action. When such a result arrives, the handler is run and the result is replaced by its
+    /// "ClientActionSucceededResult" or "ClientActionFailedResult". The outcomes are kept in
+    /// `HoundServerResponse::client_action_outcomes`, and the "ConversationState" of the last
+    /// one is sent with the user's following queries unless they set their own.
+    pub fn register_client_action<F>(&mut self, command_kind: &str, handler: F)
+    where
+        F: Fn(&Map<String, Value>) -> bool + Send + Sync + 'static,
+    {
+        self.client_actions.register(command_kind, handler);
+    }
+
+    /// Set the provider consulted before each query to fill in the location fields of the
+    /// RequestInfo. Fields set on the query itself take precedence, while the provider's
+    /// location takes precedence over the default RequestInfo.
+    pub fn set_location_provider<P: LocationProvider + 'static>(&mut self, provider: P) {
+        self.location_provider = Some(Box::new(provider));
+    }
+
+    /// Set the tracker that accumulates the credits used by each query. If the tracker has a
+    /// budget, queries fail with a `BudgetExceededError` once it is spent.
+    pub fn set_usage_tracker(&mut self, tracker: Arc<UsageTracker>) {
+        self.usage_tracker = Some(tracker);
+    }
+
+    /// A snapshot of the credits used so far, if a usage tracker is set
+    pub fn usage(&self) -> Option<UsageSnapshot> {
+        self.usage_tracker.as_ref().map(|t| t.snapshot())
+    }
+
+    /// Set the rate limiter applied to every query. `text_query` and `voice_query` wait for it,
+    /// while `try_text_query` and `try_voice_query` fail with a `RateLimitedError` instead.
+    pub fn set_rate_limiter(&mut self, limiter: RateLimiter) {
+        self.rate_limiter = Some(limiter);
+    }
+
+    /// Set the cache consulted by `text_query` and `try_text_query`. Cached responses are
+    /// returned without contacting the server, and do not count against the rate limiter or
+    /// the credit budget.
+    pub fn set_response_cache(&mut self, cache: ResponseCache) {
+        self.response_cache = Some(cache);
+    }
+
+    /// Set the base RequestInfo deep merged into every query. Fields set on the query itself
+    /// take precedence, and object fields are merged recursively.
+    ///
+    /// A few fields are managed by the client:
+    /// - "ClientID" and "TimeStamp" are always the client ID and the time used to sign the
+    ///   request, whatever the base or the query says
+    /// - "UserID" is always the user ID the query was created with
+    /// - "SDK" names this crate and its version unless the query or the base sets it
+    pub fn set_default_request_info(&mut self, request_info: RequestInfo) {
+        self.default_request_info = request_info;
+    }
+
+    /// Set the application metadata sent with every query. None is sent unless it is set here
+    /// or with `collect_client_metadata`. Fields set on the query or the base RequestInfo take
+    /// precedence.
+    pub fn set_client_metadata(&mut self, metadata: ClientMetadata) {
+        self.metadata = metadata;
+    }
+
+    /// Send the metadata of `ClientMetadata::collect()` with every query: the executable's
+    /// name, the operating system and a hash of the machine ID
+    pub fn collect_client_metadata(&mut self) {
+        self.metadata = ClientMetadata::collect();
+    }
+
+    pub fn client_metadata(&self) -> &ClientMetadata {
+        &self.metadata
+    }
+
+    /// Set where the RequestInfo is sent. Custom queries whose endpoint does not accept it in
+    /// the body always send it in the header.
+    pub fn set_request_info_placement(&mut self, placement: RequestInfoPlacement) {
+        self.request_info_placement = placement;
+    }
+
+    /// Whether to ask for gzip or deflate compressed responses. Enabled by default.
+    pub fn set_response_compression(&mut self, enabled: bool) {
+        self.response_compression = enabled;
+    }
+
+    /// Gzip the body of requests whose RequestInfo is sent in the body and is longer than
+    /// `threshold` bytes. Disabled (`None`) by default, as not every deployment accepts
+    /// compressed requests.
+    pub fn set_request_compression(&mut self, threshold: Option<usize>) {
+        self.request_compression_threshold = threshold;
+    }
+
+    /// Keep the body of each text and voice query response in `HoundServerResponse::raw`, for
+    /// bug reports. The body is then buffered before it is decoded.
+    pub fn set_keep_raw_response(&mut self, enabled: bool) {
+        self.keep_raw_response = enabled;
+    }
+
+    /// What to do with text and voice query responses whose "FormatVersion" this SDK does not
+    /// support. Defaults to logging a warning through the `log` crate. Responses of another
+    /// "Format" always fail with an `UnsupportedFormatError`.
+    pub fn set_format_version_policy(&mut self, policy: FormatVersionPolicy) {
+        self.format_version_policy = policy;
+    }
+
+    /// Bytes sent and received so far, before and after compression
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.compression.snapshot()
+    }
+
+    /// The base RequestInfo merged into every query
+    pub fn default_request_info(&self) -> &RequestInfo {
+        &self.default_request_info
+    }
+
+    /// The "ConversationState" left by the last client action run for `user_id`, if any
+    pub fn conversation_state(&self, user_id: &str) -> Option<Value> {
+        self.conversation_states
+            .lock()
+            .unwrap()
+            .get(user_id)
+            .cloned()
+    }
+
+    /// Forget the "ConversationState" left by client actions for `user_id`, starting a new
+    /// conversation
+    pub fn clear_conversation_state(&self, user_id: &str) {
+        self.conversation_states.lock().unwrap().remove(user_id);
+    }
+
+    fn cached_response(
+        &self,
+        query: &TextQuery,
+        request_info: &RequestInfo,
+    ) -> Option<HoundServerResponse> {
+        self.response_cache
+            .as_ref()
+            .and_then(|cache| cache.get(&query.query, &query.user_id, request_info))
+    }
+
+    fn acquire(&self, user_id: &str) {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire(user_id);
+        }
+    }
+
+    fn try_acquire(&self, user_id: &str) -> Result<()> {
+        match &self.rate_limiter {
+            Some(limiter) => limiter
+                .try_acquire(user_id)
+                .map_err(|e| HoundifyError::new(e.into())),
+            None => Ok(()),
+        }
+    }
+
+    fn check_budget(&self) -> Result<()> {
+        match &self.usage_tracker {
+            Some(tracker) => tracker
+                .check_budget()
+                .map_err(|e| HoundifyError::new(e.into())),
+            None => Ok(()),
+        }
+    }
+
+    fn record_usage(&self, user_id: &str, response: Option<&HoundServerResponse>) {
+        if let (Some(tracker), Some(response)) = (&self.usage_tracker, response) {
+            tracker.record(user_id, response);
         }
     }
 
@@ -61,10 +294,10 @@ impl Client {
         user_id: &str,
         request_id: &str,
         timestamp: u64,
-    ) -> std::result::Result<HeaderMap, Box<dyn std::error::Error>> {
+    ) -> std::result::Result<HeaderMap, Box<dyn std::error::Error + Send + Sync>> {
         let decoded_client_key = base64::decode_config(&self.client_key, base64::URL_SAFE)?;
         let mut mac: Hmac<Sha256> = Hmac::new_varkey(&decoded_client_key).unwrap();
-        let data = format!("{};{}{}", user_id, request_id, timestamp.to_string());
+        let data = format!("{};{}{}", user_id, request_id, timestamp);
         mac.input(data.as_bytes());
         let hmac_result = mac.result();
         let signature = base64::encode_config(&hmac_result.code(), base64::URL_SAFE);
@@ -80,95 +313,325 @@ impl Client {
         Ok(header_map)
     }
 
-    fn build_request_headers(
+    /// `request_info` merged with the client's RequestInfo fields, with the fields the client
+    /// manages set, except "TimeStamp" which is set when the request is signed
+    fn complete_request_info(&self, user_id: &str, request_info: &RequestInfo) -> RequestInfo {
+        let mut request_info = request_info.clone();
+        if !request_info.contains("ConversationState") {
+            if let Some(state) = self.conversation_state(user_id) {
+                request_info.conversation_state(state);
+            }
+        }
+        if let Some(location) = self
+            .location_provider
+            .as_ref()
+            .and_then(|provider| provider.location())
+        {
+            location.apply(&mut request_info);
+        }
+        request_info.apply_defaults(&self.default_request_info);
+        self.metadata.apply(&mut request_info);
+        if !request_info.contains("SDK") {
+            request_info.set("SDK".to_string(), Value::String(SDK.to_string()));
+        }
+        request_info.set("UserID".to_string(), Value::String(user_id.to_string()));
+        request_info.client_id(&self.client_id);
+        request_info
+    }
+
+    /// Send a text query. Pass `&query` to keep the query for sending again.
+    pub fn text_query<Q: Borrow<TextQuery>>(&self, query: Q) -> Result<HoundServerResponse> {
+        let query = query.borrow();
+        let request_info = self.complete_request_info(&query.user_id, &query.request_info);
+        if let Some(response) = self.cached_response(query, &request_info) {
+            return Ok(response);
+        }
+        self.check_budget()?;
+        self.acquire(&query.user_id);
+        self.send_text_query(query, request_info)
+    }
+
+    /// Like `text_query`, but fails with a `RateLimitedError` instead of waiting for the rate
+    /// limiter
+    pub fn try_text_query<Q: Borrow<TextQuery>>(&self, query: Q) -> Result<HoundServerResponse> {
+        let query = query.borrow();
+        let request_info = self.complete_request_info(&query.user_id, &query.request_info);
+        if let Some(response) = self.cached_response(query, &request_info) {
+            return Ok(response);
+        }
+        self.check_budget()?;
+        self.try_acquire(&query.user_id)?;
+        self.send_text_query(query, request_info)
+    }
+
+    /// Send a text query whose RequestInfo is already completed. Responses that ran client
+    /// actions are not cached.
+    fn send_text_query(
+        &self,
+        query: &TextQuery,
+        request_info: RequestInfo,
+    ) -> Result<HoundServerResponse> {
+        let response = self
+            .send_with(query, request_info.clone(), None)
+            .and_then(|r| self.parse_response(r));
+        self.record_usage(&query.user_id, response.as_ref().ok());
+        let response = response.map(|r| self.run_client_actions(&query.user_id, r));
+        if let (Some(cache), Ok(response)) = (&self.response_cache, &response) {
+            cache.insert(&query.query, &query.user_id, &request_info, response);
+        }
+        response
+    }
+
+    pub fn voice_query(&self, query: VoiceQuery) -> Result<HoundServerResponse> {
+        self.check_budget()?;
+        self.acquire(&query.user_id);
+        self.send_voice_query(query)
+    }
+
+    /// Like `voice_query`, but fails with a `RateLimitedError` instead of waiting for the rate
+    /// limiter
+    pub fn try_voice_query(&self, query: VoiceQuery) -> Result<HoundServerResponse> {
+        self.check_budget()?;
+        self.try_acquire(&query.user_id)?;
+        self.send_voice_query(query)
+    }
+
+    fn send_voice_query(&self, mut query: VoiceQuery) -> Result<HoundServerResponse> {
+        let body = query.body();
+        let response = self.send(&query, body).and_then(|r| self.parse_response(r));
+        self.record_usage(&query.user_id, response.as_ref().ok());
+        response.map(|r| self.run_client_actions(&query.user_id, r))
+    }
+
+    /// Run the handlers for the results of `response` that require a client side action, and
+    /// keep the "ConversationState" they leave for the user's next query
+    fn run_client_actions(
         &self,
         user_id: &str,
-        request_id: &str,
-        timestamp: u64,
-        request_info: &mut RequestInfo,
-    ) -> Result<HeaderMap> {
-        let mut headers = match self.build_auth_headers(user_id, &request_id, timestamp) {
-            Ok(h) => h,
-            Err(e) => return Err(HoundifyError::new(e.into())),
-        };
+        mut response: HoundServerResponse,
+    ) -> HoundServerResponse {
+        let outcomes = self.client_actions.apply(&mut response);
+        if let Some(state) = outcomes.iter().rev().find_map(|o| o.conversation_state()) {
+            self.conversation_states
+                .lock()
+                .unwrap()
+                .insert(user_id.to_string(), state.clone());
+        }
+        response.client_action_outcomes = outcomes;
+        response
+    }
 
-        &request_info.timestamp(timestamp);
-        &request_info.client_id(&self.client_id);
+    /// Like `text_query`, but only the envelope of the response is decoded up front and each
+    /// entry of "AllResults" is decoded on demand. The response is neither cached nor checked
+    /// for client actions.
+    pub fn text_query_lazy<Q: Borrow<TextQuery>>(
+        &self,
+        query: Q,
+    ) -> Result<LazyHoundServerResponse> {
+        let query = query.borrow();
+        self.check_budget()?;
+        self.acquire(&query.user_id);
+        let response = self
+            .send(query, None)
+            .and_then(|r| self.decode(r))
+            .and_then(|r| self.check_lazy_format(r));
+        self.record_lazy_usage(&query.user_id, &response);
+        response
+    }
 
-        let request_info_json = &request_info.clone().serialize()?;
-        let request_info_len = request_info_json.len();
-        headers.insert("Houndify-Request-Info", request_info_json.parse().unwrap());
-        headers.insert(
-            "Houndify-Request-Info-Length",
-            request_info_len.to_string().parse().unwrap(),
-        );
+    /// Like `voice_query`, decoding the response lazily as `text_query_lazy` does
+    pub fn voice_query_lazy(&self, mut query: VoiceQuery) -> Result<LazyHoundServerResponse> {
+        self.check_budget()?;
+        self.acquire(&query.user_id);
+        let body = query.body();
+        let response = self
+            .send(&query, body)
+            .and_then(|r| self.decode(r))
+            .and_then(|r| self.check_lazy_format(r));
+        self.record_lazy_usage(&query.user_id, &response);
+        response
+    }
+
+    fn check_lazy_format(
+        &self,
+        response: LazyHoundServerResponse,
+    ) -> Result<LazyHoundServerResponse> {
+        self.check_format(response.envelope())?;
+        Ok(response)
+    }
 
-        Ok(headers)
+    fn check_format(&self, response: &HoundServerResponse) -> Result<()> {
+        self.format_version_policy
+            .check(response)
+            .map_err(|e| HoundifyError::new(e.into()))
     }
 
-    pub fn text_query(&self, mut query: TextQuery) -> Result<HoundServerResponse> {
+    fn record_lazy_usage(&self, user_id: &str, response: &Result<LazyHoundServerResponse>) {
+        self.record_usage(user_id, response.as_ref().ok().map(|r| r.envelope()));
+    }
+
+    /// Send any `Query`, decoding the response into its `Response` type. The query waits for
+    /// the rate limiter and fails once the credit budget is spent, but unlike text and voice
+    /// queries its response is not cached, counted against the budget or checked for client
+    /// actions.
+    pub fn execute<Q: Query>(&self, mut query: Q) -> Result<Q::Response> {
+        self.check_budget()?;
+        self.acquire(query.user_id());
+        let body = query.body();
+        let response = self.send(&query, body)?;
+        self.decode(response)
+    }
+
+    /// Sign and send `query` with its RequestInfo merged with the client's. The RequestInfo
+    /// goes in the "Houndify-Request-Info" header, or at the start of the body if the query
+    /// accepts that and the placement calls for it.
+    fn send<Q: Query>(&self, query: &Q, body: Option<QueryBody>) -> Result<HttpResponse> {
+        let request_info = self.complete_request_info(query.user_id(), query.request_info());
+        self.send_with(query, request_info, body)
+    }
+
+    /// Like `send`, with the RequestInfo already completed by `complete_request_info`
+    fn send_with<Q: Query>(
+        &self,
+        query: &Q,
+        mut request_info: RequestInfo,
+        body: Option<QueryBody>,
+    ) -> Result<HttpResponse> {
         let timestamp = get_current_timestamp();
-        let request_id = (&self.request_id_generator)();
-        let headers = self.build_request_headers(
-            &query.user_id,
-            &request_id,
-            timestamp,
-            &mut query.request_info,
-        )?;
-        let url = query.get_url(&self.api_url);
-        let req = self.http_client.get(&url).headers(headers);
-        println!("Request={:#?}", req);
-
-        match req.send() {
-            Ok(r) => self.parse_response(r),
-            Err(e) => {
-                println!("Error={:#?}", e);
-                Err(HoundifyError::new(e.into()))
+        let request_id = (self.request_id_generator)();
+        let mut headers = query.headers();
+        headers.extend(
+            self.build_auth_headers(query.user_id(), &request_id, timestamp)
+                .map_err(HoundifyError::new)?,
+        );
+        request_info.timestamp(timestamp);
+        let request_info_json = request_info.serialize()?;
+        headers.insert(
+            "Houndify-Request-Info-Length",
+            request_info_json.len().to_string().parse().unwrap(),
+        );
+
+        let mut method = query.method();
+        let header_value = HeaderValue::from_str(&request_info_json).ok();
+        let in_body = query.accepts_request_info_body()
+            && match (self.request_info_placement, &header_value) {
+                (RequestInfoPlacement::Header, _) => false,
+                (RequestInfoPlacement::Body, _) => true,
+                (RequestInfoPlacement::Auto(max_len), Some(_)) => request_info_json.len() > max_len,
+                (RequestInfoPlacement::Auto(_), None) => true,
+            };
+        let body = if in_body {
+            if method == Method::GET {
+                method = Method::POST;
             }
+            let compress = matches!(
+                self.request_compression_threshold,
+                Some(threshold) if request_info_json.len() > threshold
+            );
+            let prefix = request_info_json.into_bytes();
+            let body = match body {
+                None => QueryBody::Bytes(prefix),
+                Some(QueryBody::Bytes(bytes)) => QueryBody::Bytes([prefix, bytes].concat()),
+                Some(QueryBody::Reader(reader)) => {
+                    QueryBody::Reader(Box::new(Cursor::new(prefix).chain(reader)))
+                }
+            };
+            if compress {
+                headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+                Some(self.compress(body)?)
+            } else {
+                Some(body)
+            }
+        } else {
+            match header_value {
+                Some(v) => headers.insert("Houndify-Request-Info", v),
+                None => {
+                    let msg = "RequestInfo is not a valid header value";
+                    return Err(HoundifyError::new(msg.into()));
+                }
+            };
+            body
+        };
+
+        if self.response_compression && !headers.contains_key(ACCEPT_ENCODING) {
+            headers.insert(
+                ACCEPT_ENCODING,
+                HeaderValue::from_static(compression::ACCEPT_ENCODING),
+            );
         }
-    }
 
-    pub fn voice_query(&self, mut query: VoiceQuery) -> Result<HoundServerResponse> {
-        let timestamp = get_current_timestamp();
-        let request_id = (&self.request_id_generator)();
-        let headers = self.build_request_headers(
-            &query.user_id,
-            &request_id,
-            timestamp,
-            &mut query.request_info,
-        )?;
         let url = query.get_url(&self.api_url);
-        let req = self
-            .http_client
-            .post(&url)
-            .body(Body::new(query.audio_stream))
-            .headers(headers);
-        println!("Request={:#?}", req);
-        match req.send() {
-            Ok(r) => self.parse_response(r),
-            Err(e) => {
-                println!("Error={:#?}", e);
-                Err(HoundifyError::new(e.into()))
+        let mut req = self.http_client.request(method, &url).headers(headers);
+        req = match body {
+            Some(QueryBody::Bytes(bytes)) => req.body(bytes),
+            Some(QueryBody::Reader(reader)) => req.body(Body::new(reader)),
+            None => req,
+        };
+        log::debug!("Sending {}", url);
+        req.send().map_err(|e| {
+            log::debug!("Request to {} failed: {}", url, e);
+            HoundifyError::new(e.into())
+        })
+    }
+
+    /// Gzip a request body. Bytes are compressed up front so the request keeps its
+    /// Content-Length, while readers are compressed as they are streamed.
+    fn compress(&self, body: QueryBody) -> Result<QueryBody> {
+        match body {
+            QueryBody::Bytes(bytes) => {
+                let mut compressed = vec![];
+                compression::gzip(Cursor::new(bytes), &self.compression)
+                    .read_to_end(&mut compressed)
+                    .map_err(|e| HoundifyError::new(e.into()))?;
+                Ok(QueryBody::Bytes(compressed))
             }
+            QueryBody::Reader(reader) => Ok(QueryBody::Reader(Box::new(compression::gzip(
+                reader,
+                &self.compression,
+            )))),
         }
     }
 
-    fn parse_response(&self, res: reqwest::blocking::Response) -> Result<HoundServerResponse> {
-        match res.text() {
-            Ok(res) => {
-                match serde_json::from_str(&res) {
-                    Ok(json) => Ok(json),
-                    Err(e) => Err(HoundifyError::new(e.into())),
-                }
-            },
-            Err(e) => Err(HoundifyError::new(e.into())),
-        }
+    fn parse_response(&self, res: HttpResponse) -> Result<HoundServerResponse> {
+        let response = if self.keep_raw_response {
+            let mut raw = vec![];
+            self.body(res)?
+                .read_to_end(&mut raw)
+                .map_err(|e| HoundifyError::new(e.into()))?;
+            let mut response: HoundServerResponse =
+                serde_json::from_slice(&raw).map_err(|e| HoundifyError::new(e.into()))?;
+            response.raw = Some(raw);
+            response
+        } else {
+            self.decode(res)?
+        };
+        self.check_format(&response)?;
+        Ok(response)
+    }
+
+    /// Decode a JSON response as it streams in
+    fn decode<R: DeserializeOwned>(&self, res: HttpResponse) -> Result<R> {
+        let body = self.body(res)?;
+        serde_json::from_reader(BufReader::new(body)).map_err(|e| HoundifyError::new(e.into()))
+    }
+
+    /// The response body, decompressed if needed
+    fn body(&self, res: HttpResponse) -> Result<Box<dyn Read>> {
+        let encoding = res
+            .headers()
+            .get(CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        compression::decoder(encoding.as_deref(), res, &self.compression)
     }
 }
 
 #[cfg(test)]
 mod client_tests {
     use super::*;
+    use crate::error::UnsupportedFormatError;
+    use crate::test_server;
+    use serde_json::json;
 
     #[test]
     fn test_generate_auth_values() {
@@ -188,4 +651,421 @@ mod client_tests {
             "test_user;deadbeef"
         );
     }
+
+    #[test]
+    fn test_default_request_info_precedence() {
+        let url = test_server::serve(|request| {
+            let request_info = &request.headers["houndify-request-info"];
+            test_server::ok_response(request_info, "Arithmetic", 0.5)
+        });
+        let mut client = Client::new(&url, "client", "a2V5", None);
+        let base: Map<String, Value> = serde_json::from_value(json!({
+            "UnitPreference": "METRIC",
+            "ClientState": {"Volume": 5, "Player": {"Shuffle": true}},
+            "ClientID": "someone-else",
+            "UserID": "someone-else",
+            "TimeStamp": 0,
+        }))
+        .unwrap();
+        client.set_default_request_info(RequestInfo::from(base));
+        client.set_client_metadata(ClientMetadata::new().client_version("3.0"));
+
+        let mut request_info = RequestInfo::new();
+        request_info.set("UnitPreference".to_string(), json!("US"));
+        request_info.set(
+            "ClientState".to_string(),
+            json!({"Player": {"Repeat": true}}),
+        );
+        request_info.set("SDK".to_string(), json!("my-wrapper/2.0"));
+        let response = client
+            .text_query(TextQuery::new("one plus one", "user", request_info))
+            .unwrap();
+
+        let sent = &response.all_results.unwrap()[0]["WrittenResponse"];
+        let mut sent: Value = serde_json::from_str(sent.as_str().unwrap()).unwrap();
+        assert_ne!(sent["TimeStamp"], 0);
+        sent.as_object_mut().unwrap().remove("TimeStamp");
+        assert_eq!(
+            sent,
+            json!({
+                "UnitPreference": "US",
+                "ClientState": {"Volume": 5, "Player": {"Shuffle": true, "Repeat": true}},
+                "ClientID": "client",
+                "UserID": "user",
+                "SDK": "my-wrapper/2.0",
+                "ClientVersion": "3.0",
+            })
+        );
+    }
+
+    #[test]
+    fn test_client_metadata_is_opt_in() {
+        let url = test_server::serve(|request| {
+            let request_info = &request.headers["houndify-request-info"];
+            test_server::ok_response(request_info, "Arithmetic", 0.5)
+        });
+        let mut client = Client::new(&url, "client", "a2V5", None);
+        let query = TextQuery::new("one plus one", "user", RequestInfo::new());
+        let sent = |response: HoundServerResponse| -> Value {
+            let sent = &response.all_results.unwrap()[0]["WrittenResponse"];
+            serde_json::from_str(sent.as_str().unwrap()).unwrap()
+        };
+
+        let plain = sent(client.text_query(&query).unwrap());
+        assert!(plain.get("AppName").is_none());
+        assert!(plain.get("OperatingSystem").is_none());
+        assert!(plain.get("DeviceID").is_none());
+
+        client.collect_client_metadata();
+        let collected = sent(client.text_query(&query).unwrap());
+        assert!(collected["OperatingSystem"].is_string());
+    }
+
+    #[test]
+    fn test_queries_are_owned_and_reusable() {
+        fn assert_send_static<T: Send + 'static>() {}
+        assert_send_static::<TextQuery>();
+        assert_send_static::<VoiceQuery>();
+
+        let url = test_server::serve(|request| {
+            let request_info: Value =
+                serde_json::from_str(&request.headers["houndify-request-info"]).unwrap();
+            let written = request_info["UserID"].as_str().unwrap().to_string();
+            test_server::ok_response(&written, "Arithmetic", 0.5)
+        });
+        let client = Client::new(&url, "client", "a2V5", None);
+        let query = std::thread::spawn(|| {
+            let user_id = format!("user{}", 1);
+            TextQuery::new("one plus one", user_id, RequestInfo::new())
+        })
+        .join()
+        .unwrap();
+
+        for _ in 0..2 {
+            let response = client.text_query(&query).unwrap();
+            assert_eq!(response.all_results.unwrap()[0]["WrittenResponse"], "user1");
+        }
+        assert!(!query.request_info().contains("UserID"));
+        assert_eq!(query.query(), "one plus one");
+        client.text_query(query.clone()).unwrap();
+    }
+
+    #[test]
+    fn test_execute_custom_query() {
+        #[derive(serde::Deserialize)]
+        struct Echo {
+            method: String,
+            path: String,
+            trace: String,
+            user_id: String,
+            body: String,
+        }
+
+        struct EchoQuery {
+            request_info: RequestInfo,
+        }
+
+        impl Query for EchoQuery {
+            type Response = Echo;
+
+            fn get_url(&self, api_url: &str) -> String {
+                format!("{}v1/echo", api_url)
+            }
+
+            fn user_id(&self) -> &str {
+                "user"
+            }
+
+            fn request_info(&self) -> &RequestInfo {
+                &self.request_info
+            }
+
+            fn method(&self) -> reqwest::Method {
+                reqwest::Method::PUT
+            }
+
+            fn headers(&self) -> HeaderMap {
+                let mut headers = HeaderMap::new();
+                headers.insert("X-Trace", "abc".parse().unwrap());
+                headers
+            }
+
+            fn body(&mut self) -> Option<QueryBody> {
+                Some(QueryBody::Bytes(b"hello".to_vec()))
+            }
+        }
+
+        let url = test_server::serve(|request| {
+            let request_info: Value =
+                serde_json::from_str(&request.headers["houndify-request-info"]).unwrap();
+            json!({
+                "method": request.method,
+                "path": request.path,
+                "trace": request.headers["x-trace"],
+                "user_id": request_info["UserID"],
+                "body": String::from_utf8(request.body.clone()).unwrap(),
+            })
+            .to_string()
+        });
+        let client = Client::new(&url, "client", "a2V5", None);
+        let echo = client
+            .execute(EchoQuery {
+                request_info: RequestInfo::new(),
+            })
+            .unwrap();
+        assert_eq!(echo.method, "PUT");
+        assert_eq!(echo.path, "/v1/echo");
+        assert_eq!(echo.trace, "abc");
+        assert_eq!(echo.user_id, "user");
+        assert_eq!(echo.body, "hello");
+    }
+
+    /// Echoes where the RequestInfo arrived: "header" or "body", followed by the rest of the body
+    fn placement_server() -> String {
+        test_server::serve(|request| {
+            let len: usize = request.headers["houndify-request-info-length"]
+                .parse()
+                .unwrap();
+            let written = if request.headers.contains_key("houndify-request-info") {
+                assert_eq!(request.headers["houndify-request-info"].len(), len);
+                format!("{} header {}", request.method, request.body.len())
+            } else {
+                let (request_info, rest) = request.body.split_at(len);
+                let request_info: Value = serde_json::from_slice(request_info).unwrap();
+                assert_eq!(request_info["UserID"], "user");
+                let rest = String::from_utf8(rest.to_vec()).unwrap();
+                format!("{} body {}", request.method, rest)
+            };
+            test_server::ok_response(&written, "Arithmetic", 0.5)
+        })
+    }
+
+    fn written(response: Result<HoundServerResponse>) -> String {
+        let results = response.unwrap().all_results.unwrap();
+        results[0]["WrittenResponse"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_request_info_placement() {
+        let mut client = Client::new(&placement_server(), "client", "a2V5", None);
+        let query = TextQuery::new("one plus one", "user", RequestInfo::new());
+        assert_eq!(written(client.text_query(&query)), "GET header 0");
+
+        let mut large = RequestInfo::new();
+        large.conversation_state(
+            json!({ "History": "x".repeat(DEFAULT_MAX_REQUEST_INFO_HEADER_LEN) }),
+        );
+        let large = TextQuery::new("one plus one", "user", large);
+        assert_eq!(written(client.text_query(&large)), "POST body ");
+
+        client.set_request_info_placement(RequestInfoPlacement::Body);
+        let audio = VoiceQuery::new(Box::new(&b"audio"[..]), "user", RequestInfo::new());
+        assert_eq!(written(client.voice_query(audio)), "POST body audio");
+
+        client.set_request_info_placement(RequestInfoPlacement::Header);
+        let audio = VoiceQuery::new(Box::new(&b"audio"[..]), "user", RequestInfo::new());
+        assert_eq!(written(client.voice_query(audio)), "POST header 5");
+        assert_eq!(written(client.text_query(&large)), "GET header 0");
+    }
+
+    #[test]
+    fn test_compression() {
+        use flate2::read::{GzDecoder, GzEncoder};
+
+        let url = test_server::serve_with_headers(|request| {
+            assert_eq!(request.headers["accept-encoding"], "gzip, deflate");
+            let mut body = String::new();
+            GzDecoder::new(&request.body[..])
+                .read_to_string(&mut body)
+                .unwrap();
+            let len: usize = request.headers["houndify-request-info-length"]
+                .parse()
+                .unwrap();
+            let request_info: Value = serde_json::from_str(&body[..len]).unwrap();
+            let state = request_info["ConversationState"]["History"]
+                .as_str()
+                .unwrap();
+
+            let response = test_server::ok_response(state, "Arithmetic", 0.5);
+            let mut compressed = vec![];
+            GzEncoder::new(response.as_bytes(), flate2::Compression::default())
+                .read_to_end(&mut compressed)
+                .unwrap();
+            (vec![("Content-Encoding", "gzip".to_string())], compressed)
+        });
+        let mut client = Client::new(&url, "client", "a2V5", None);
+        client.set_request_info_placement(RequestInfoPlacement::Body);
+        client.set_request_compression(Some(1024));
+
+        let history = "one plus one ".repeat(500);
+        let mut request_info = RequestInfo::new();
+        request_info.conversation_state(json!({ "History": history }));
+        let query = TextQuery::new("one plus one", "user", request_info);
+        assert_eq!(written(client.text_query(&query)), history);
+
+        let stats = client.compression_stats();
+        assert_eq!(stats.compressed_requests, 1);
+        assert_eq!(stats.compressed_responses, 1);
+        assert!(stats.request_bytes_uncompressed > history.len() as u64);
+        assert!(stats.response_bytes_decoded > history.len() as u64);
+        assert!(stats.bytes_saved() > history.len() as u64);
+    }
+
+    #[test]
+    fn test_text_query_lazy() {
+        let url = test_server::serve(|_| test_server::ok_response("2", "Arithmetic", 0.5));
+        let mut client = Client::new(&url, "client", "a2V5", None);
+        let tracker = Arc::new(UsageTracker::new(std::time::Duration::from_secs(60)));
+        client.set_usage_tracker(tracker.clone());
+
+        let query = TextQuery::new("one plus one", "user", RequestInfo::new());
+        let response = client.text_query_lazy(&query).unwrap();
+        assert!(response.envelope().is_ok());
+        assert_eq!(response.len(), 1);
+        assert_eq!(response.result(0).unwrap().unwrap()["WrittenResponse"], "2");
+        assert_eq!(tracker.snapshot().all_time.credits, 0.5);
+    }
+
+    #[test]
+    fn test_keep_raw_response() {
+        let url = test_server::serve(|_| test_server::ok_response("2", "Arithmetic", 0.5));
+        let mut client = Client::new(&url, "client", "a2V5", None);
+        let query = TextQuery::new("one plus one", "user", RequestInfo::new());
+        assert!(client.text_query(&query).unwrap().raw.is_none());
+
+        client.set_keep_raw_response(true);
+        let response = client.text_query(&query).unwrap();
+        let raw = response.raw.unwrap();
+        assert_eq!(
+            raw,
+            test_server::ok_response("2", "Arithmetic", 0.5).into_bytes()
+        );
+    }
+
+    #[test]
+    fn test_client_action_conversation_state() {
+        let url = test_server::serve(|request| {
+            let request_info: Value =
+                serde_json::from_str(&request.headers["houndify-request-info"]).unwrap();
+            json!({
+                "Format": "SoundHoundVoiceSearchResult",
+                "FormatVersion": "1.0",
+                "Status": "OK",
+                "QueryID": "deadbeef",
+                "AllResults": [{
+                    "CommandKind": "AlarmCommand",
+                    "ClientActionSucceededResult": {
+                        "WrittenResponse": request_info["ConversationState"],
+                        "ConversationState": {"Alarm": "set"},
+                    },
+                    "ClientActionFailedResult": {"ConversationState": {"Alarm": "failed"}},
+                }],
+            })
+            .to_string()
+        });
+        let mut client = Client::new(&url, "client", "a2V5", None);
+        client.register_client_action("AlarmCommand", |_| true);
+
+        let query = TextQuery::new("set an alarm", "user", RequestInfo::new());
+        let first = client.text_query(&query).unwrap();
+        assert_eq!(
+            first.all_results.unwrap()[0]["WrittenResponse"],
+            Value::Null
+        );
+        assert_eq!(first.client_action_outcomes.len(), 1);
+        assert!(first.client_action_outcomes[0].succeeded);
+
+        let second = client.text_query(&query).unwrap();
+        assert_eq!(
+            second.all_results.unwrap()[0]["WrittenResponse"],
+            json!({"Alarm": "set"})
+        );
+        assert_eq!(
+            client.conversation_state("user"),
+            Some(json!({"Alarm": "set"}))
+        );
+        assert_eq!(client.conversation_state("other"), None);
+
+        client.clear_conversation_state("user");
+        let third = client.text_query(&query).unwrap();
+        assert_eq!(
+            third.all_results.unwrap()[0]["WrittenResponse"],
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn test_cached_client_actions() {
+        use crate::cache::ResponseCache;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let url = test_server::serve(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            json!({
+                "Format": "SoundHoundVoiceSearchResult",
+                "FormatVersion": "1.0",
+                "Status": "OK",
+                "QueryID": "deadbeef",
+                "AllResults": [{
+                    "CommandKind": "AlarmCommand",
+                    "ClientActionSucceededResult": {"SpokenResponse": "Alarm set."},
+                    "ClientActionFailedResult": {"SpokenResponse": "Sorry."},
+                }],
+            })
+            .to_string()
+        });
+        let cache = || ResponseCache::new(std::time::Duration::from_secs(60), 10);
+        let mut client = Client::new(&url, "client", "a2V5", None);
+        client.set_response_cache(cache());
+        let handled = Arc::new(AtomicUsize::new(0));
+        let counter = handled.clone();
+        client.register_client_action("AlarmCommand", move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            true
+        });
+
+        let query = TextQuery::new("set an alarm", "user", RequestInfo::new());
+        for _ in 0..2 {
+            let results = client.text_query(&query).unwrap().all_results.unwrap();
+            assert_eq!(results[0]["SpokenResponse"], "Alarm set.");
+        }
+        // Responses that ran a client action are not cached, so the action runs every time
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(handled.load(Ordering::SeqCst), 2);
+
+        // Without a handler the response is cached. The key covers the user and the
+        // RequestInfo as sent, defaults included.
+        let mut client = Client::new(&url, "client", "a2V5", None);
+        client.set_response_cache(cache());
+        client.text_query(&query).unwrap();
+        client.text_query(&query).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        let other_user = TextQuery::new("set an alarm", "other", RequestInfo::new());
+        client.text_query(&other_user).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+        let mut french = RequestInfo::new();
+        french.input_language_ietf_tag("fr");
+        client.set_default_request_info(french);
+        client.text_query(&query).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn test_format_version_policy() {
+        let url = test_server::serve(|_| {
+            test_server::ok_response("2", "Arithmetic", 0.5).replace("\"1.0\"", "\"2.0\"")
+        });
+        let mut client = Client::new(&url, "client", "a2V5", None);
+        client.set_format_version_policy(FormatVersionPolicy::Accept);
+        let query = TextQuery::new("one plus one", "user", RequestInfo::new());
+        assert_eq!(client.text_query(&query).unwrap().format_version, "2.0");
+
+        client.set_format_version_policy(FormatVersionPolicy::Reject);
+        let err = client.text_query(&query).unwrap_err();
+        let err = err.downcast_ref::<UnsupportedFormatError>().unwrap();
+        assert_eq!(err.format_version(), "2.0");
+        assert!(client.text_query_lazy(&query).is_err());
+    }
 }
@@ -0,0 +1,184 @@
+use crate::query::RequestInfo;
+use crate::response::HoundServerResponse;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+
+/// Performs the client side part of a command and returns whether it succeeded
+pub type ClientActionHandler = Box<dyn Fn(&Map<String, Value>) -> bool + Send + Sync>;
+
+/// The follow-up chosen after running a client action
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientActionOutcome {
+    /// Whether the handler reported success
+    pub succeeded: bool,
+
+    /// Either "ClientActionSucceededResult" or "ClientActionFailedResult" of the original result
+    pub result: Map<String, Value>,
+}
+
+impl ClientActionOutcome {
+    /// The "ConversationState" to send with the next query
+    pub fn conversation_state(&self) -> Option<&Value> {
+        self.result.get("ConversationState")
+    }
+
+    /// Set the "ConversationState" of the next query's RequestInfo
+    pub fn apply(&self, request_info: &mut RequestInfo) {
+        if let Some(state) = self.conversation_state() {
+            request_info.conversation_state(state.clone());
+        }
+    }
+}
+
+/// Handlers for commands that require the client to perform an action, keyed on "CommandKind".
+///
+/// Reference: https://docs.houndify.com/reference/CommandResult
+#[derive(Default)]
+pub struct ClientActions {
+    handlers: HashMap<String, ClientActionHandler>,
+}
+
+impl ClientActions {
+    pub fn new() -> Self {
+        ClientActions {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Register the handler for results of the given "CommandKind", replacing any previous one
+    pub fn register<F>(&mut self, command_kind: &str, handler: F)
+    where
+        F: Fn(&Map<String, Value>) -> bool + Send + Sync + 'static,
+    {
+        self.handlers
+            .insert(command_kind.to_string(), Box::new(handler));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// Run the handler registered for this result's "CommandKind", if any, and pick the
+    /// matching follow-up result.
+    ///
+    /// Returns `None`, without calling the handler, if no handler is registered or the result
+    /// does not carry both follow-up results as objects.
+    pub fn run(&self, result: &Map<String, Value>) -> Option<ClientActionOutcome> {
+        let command_kind = result.get("CommandKind").and_then(Value::as_str)?;
+        let handler = self.handlers.get(command_kind)?;
+        let follow_up = |key| result.get(key).and_then(Value::as_object);
+        let on_success = follow_up("ClientActionSucceededResult")?;
+        let on_failure = follow_up("ClientActionFailedResult")?;
+        let succeeded = handler(result);
+        let follow_up = if succeeded { on_success } else { on_failure };
+        Some(ClientActionOutcome {
+            succeeded,
+            result: follow_up.clone(),
+        })
+    }
+
+    /// Run the handlers for every entry of "AllResults", replacing each handled entry with its
+    /// follow-up result
+    pub fn apply(&self, response: &mut HoundServerResponse) -> Vec<ClientActionOutcome> {
+        let mut outcomes = vec![];
+        if let Some(results) = response.all_results.as_mut() {
+            for result in results.iter_mut() {
+                if let Some(outcome) = self.run(result) {
+                    *result = outcome.result.clone();
+                    outcomes.push(outcome);
+                }
+            }
+        }
+        outcomes
+    }
+}
+
+impl Debug for ClientActions {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_set().entries(self.handlers.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod client_action_tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    fn alarm_result() -> Map<String, Value> {
+        json!({
+            "CommandKind": "AlarmCommand",
+            "SpokenResponse": "Setting an alarm.",
+            "ClientActionSucceededResult": {
+                "SpokenResponse": "Alarm set.",
+                "ConversationState": {"Alarm": "set"}
+            },
+            "ClientActionFailedResult": {
+                "SpokenResponse": "Sorry, I couldn't set the alarm.",
+                "ConversationState": {"Alarm": "failed"}
+            }
+        })
+        .as_object()
+        .unwrap()
+        .clone()
+    }
+
+    #[test]
+    fn test_run_picks_follow_up_result() {
+        let mut actions = ClientActions::new();
+        actions.register("AlarmCommand", |_| true);
+        let outcome = actions.run(&alarm_result()).unwrap();
+        assert!(outcome.succeeded);
+        assert_eq!(
+            outcome.conversation_state().unwrap(),
+            &json!({"Alarm": "set"})
+        );
+
+        actions.register("AlarmCommand", |_| false);
+        let outcome = actions.run(&alarm_result()).unwrap();
+        assert!(!outcome.succeeded);
+        assert_eq!(
+            outcome.result["SpokenResponse"],
+            "Sorry, I couldn't set the alarm."
+        );
+    }
+
+    #[test]
+    fn test_run_without_handler() {
+        let actions = ClientActions::new();
+        assert_eq!(actions.run(&alarm_result()), None);
+    }
+
+    #[test]
+    fn test_run_without_follow_up() {
+        let called = Arc::new(AtomicBool::new(false));
+        let mut actions = ClientActions::new();
+        let flag = called.clone();
+        actions.register("AlarmCommand", move |_| {
+            flag.store(true, Ordering::SeqCst);
+            true
+        });
+
+        let mut result = alarm_result();
+        result.remove("ClientActionFailedResult");
+        assert_eq!(actions.run(&result), None);
+        result.insert("ClientActionFailedResult".to_string(), json!("failed"));
+        assert_eq!(actions.run(&result), None);
+        assert!(!called.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_apply_sets_conversation_state() {
+        let mut actions = ClientActions::new();
+        actions.register("AlarmCommand", |_| true);
+        let outcome = actions.run(&alarm_result()).unwrap();
+        let mut request_info = RequestInfo::new();
+        outcome.apply(&mut request_info);
+        assert_eq!(
+            request_info.serialize().unwrap(),
+            r#"{"ConversationState":{"Alarm":"set"}}"#
+        );
+    }
+}
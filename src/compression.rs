@@ -0,0 +1,205 @@
+use crate::client::Result;
+use crate::error::HoundifyError;
+use flate2::read::{DeflateDecoder, GzDecoder, GzEncoder, ZlibDecoder};
+use flate2::Compression;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Value of the "Accept-Encoding" header sent when response compression is enabled
+pub(crate) static ACCEPT_ENCODING: &str = "gzip, deflate";
+
+/// Bytes sent and received by a client, before and after compression
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CompressionStats {
+    /// Responses that arrived compressed
+    pub compressed_responses: u64,
+    /// Response bytes as received
+    pub response_bytes_received: u64,
+    /// Response bytes after decompression
+    pub response_bytes_decoded: u64,
+    /// Requests whose body was compressed
+    pub compressed_requests: u64,
+    /// Bytes of compressed request bodies before compression
+    pub request_bytes_uncompressed: u64,
+    /// Bytes of compressed request bodies as sent
+    pub request_bytes_sent: u64,
+}
+
+impl CompressionStats {
+    /// Bytes compression kept off the wire, in both directions
+    pub fn bytes_saved(&self) -> u64 {
+        let responses = self
+            .response_bytes_decoded
+            .saturating_sub(self.response_bytes_received);
+        let requests = self
+            .request_bytes_uncompressed
+            .saturating_sub(self.request_bytes_sent);
+        responses + requests
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CompressionCounters {
+    compressed_responses: AtomicU64,
+    response_bytes_received: AtomicU64,
+    response_bytes_decoded: AtomicU64,
+    compressed_requests: AtomicU64,
+    request_bytes_uncompressed: AtomicU64,
+    request_bytes_sent: AtomicU64,
+}
+
+impl CompressionCounters {
+    pub(crate) fn snapshot(&self) -> CompressionStats {
+        CompressionStats {
+            compressed_responses: self.compressed_responses.load(Ordering::Relaxed),
+            response_bytes_received: self.response_bytes_received.load(Ordering::Relaxed),
+            response_bytes_decoded: self.response_bytes_decoded.load(Ordering::Relaxed),
+            compressed_requests: self.compressed_requests.load(Ordering::Relaxed),
+            request_bytes_uncompressed: self.request_bytes_uncompressed.load(Ordering::Relaxed),
+            request_bytes_sent: self.request_bytes_sent.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counts the bytes read through it into one of the counters
+struct CountingReader<R> {
+    inner: R,
+    counters: Arc<CompressionCounters>,
+    counter: fn(&CompressionCounters) -> &AtomicU64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        (self.counter)(&self.counters).fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+fn counting<R: Read>(
+    inner: R,
+    counters: &Arc<CompressionCounters>,
+    counter: fn(&CompressionCounters) -> &AtomicU64,
+) -> CountingReader<R> {
+    CountingReader {
+        inner,
+        counters: counters.clone(),
+        counter,
+    }
+}
+
+/// Wrap a response body with the decoder for its "Content-Encoding"
+pub(crate) fn decoder<R: Read + 'static>(
+    encoding: Option<&str>,
+    body: R,
+    counters: &Arc<CompressionCounters>,
+) -> Result<Box<dyn Read>> {
+    let received = counting(body, counters, |c| &c.response_bytes_received);
+    let encoding = encoding.map(|e| e.trim().to_ascii_lowercase());
+    let decoded: Box<dyn Read> = match encoding.as_deref() {
+        None | Some("") | Some("identity") => {
+            return Ok(Box::new(counting(received, counters, |c| {
+                &c.response_bytes_decoded
+            })))
+        }
+        Some("gzip") | Some("x-gzip") => Box::new(GzDecoder::new(received)),
+        Some("deflate") => deflate_decoder(received),
+        Some(e) => {
+            let msg = format!("Unsupported response Content-Encoding {:?}", e);
+            return Err(HoundifyError::new(msg.into()));
+        }
+    };
+    counters
+        .compressed_responses
+        .fetch_add(1, Ordering::Relaxed);
+    Ok(Box::new(counting(decoded, counters, |c| {
+        &c.response_bytes_decoded
+    })))
+}
+
+/// HTTP "deflate" should be zlib wrapped, but some servers send a raw deflate stream. A zlib
+/// stream starts with a header whose first byte has 8 in its low nibble and which, read as a
+/// big endian u16, is a multiple of 31.
+fn deflate_decoder<R: Read + 'static>(body: R) -> Box<dyn Read> {
+    let mut body = BufReader::new(body);
+    let is_zlib = match body.fill_buf() {
+        Ok(b) if b.len() >= 2 => b[0] & 0x0f == 8 && u16::from_be_bytes([b[0], b[1]]) % 31 == 0,
+        _ => false,
+    };
+    if is_zlib {
+        Box::new(ZlibDecoder::new(body))
+    } else {
+        Box::new(DeflateDecoder::new(body))
+    }
+}
+
+/// Gzip a request body as it is read
+pub(crate) fn gzip<R: Read + Send + 'static>(
+    body: R,
+    counters: &Arc<CompressionCounters>,
+) -> impl Read + Send + 'static {
+    counters.compressed_requests.fetch_add(1, Ordering::Relaxed);
+    let uncompressed = counting(body, counters, |c| &c.request_bytes_uncompressed);
+    let compressed = GzEncoder::new(uncompressed, Compression::default());
+    counting(compressed, counters, |c| &c.request_bytes_sent)
+}
+
+#[cfg(test)]
+mod compression_tests {
+    use super::*;
+    use flate2::write::{DeflateEncoder, ZlibEncoder};
+    use std::io::{Cursor, Write};
+
+    fn decode(encoding: Option<&str>, body: Vec<u8>) -> String {
+        let counters = Arc::new(CompressionCounters::default());
+        let mut decoded = String::new();
+        decoder(encoding, Cursor::new(body), &counters)
+            .unwrap()
+            .read_to_string(&mut decoded)
+            .unwrap();
+        decoded
+    }
+
+    #[test]
+    fn test_decoders() {
+        let text = "{\"Status\": \"OK\"}".repeat(10);
+        let mut zlib = ZlibEncoder::new(vec![], Compression::default());
+        zlib.write_all(text.as_bytes()).unwrap();
+        let mut deflate = DeflateEncoder::new(vec![], Compression::default());
+        deflate.write_all(text.as_bytes()).unwrap();
+
+        assert_eq!(decode(None, text.clone().into_bytes()), text);
+        assert_eq!(decode(Some("deflate"), zlib.finish().unwrap()), text);
+        assert_eq!(decode(Some("Deflate"), deflate.finish().unwrap()), text);
+        let counters = Arc::new(CompressionCounters::default());
+        assert!(decoder(Some("br"), Cursor::new(vec![]), &counters).is_err());
+    }
+
+    #[test]
+    fn test_gzip_round_trip_and_stats() {
+        let text = "{\"ConversationState\": {}}".repeat(100);
+        let counters = Arc::new(CompressionCounters::default());
+        let mut compressed = vec![];
+        gzip(Cursor::new(text.clone().into_bytes()), &counters)
+            .read_to_end(&mut compressed)
+            .unwrap();
+        let mut decoded = String::new();
+        decoder(Some("gzip"), Cursor::new(compressed.clone()), &counters)
+            .unwrap()
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, text);
+
+        let stats = counters.snapshot();
+        assert_eq!(stats.compressed_requests, 1);
+        assert_eq!(stats.compressed_responses, 1);
+        assert_eq!(stats.request_bytes_uncompressed, text.len() as u64);
+        assert_eq!(stats.request_bytes_sent, compressed.len() as u64);
+        assert_eq!(stats.response_bytes_received, compressed.len() as u64);
+        assert_eq!(
+            stats.bytes_saved(),
+            2 * (text.len() - compressed.len()) as u64
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use crate::client::{Client, Result, DEFAULT_API_ENDPOINT};
+use crate::error::{ConfigError, HoundifyError};
+use crate::query::{merge_map, RequestInfo};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+
+/// Environment variable holding the client ID
+pub static ENV_CLIENT_ID: &str = "HOUNDIFY_CLIENT_ID";
+/// Environment variable holding the client key
+pub static ENV_CLIENT_KEY: &str = "HOUNDIFY_CLIENT_KEY";
+/// Environment variable holding the API URL
+pub static ENV_API_URL: &str = "HOUNDIFY_API_URL";
+/// Environment variable naming the config file profile to use
+pub static ENV_PROFILE: &str = "HOUNDIFY_PROFILE";
+
+fn config_error(msg: &str) -> HoundifyError {
+    HoundifyError::new(ConfigError::new(msg).into())
+}
+
+/// Credentials, endpoint and default RequestInfo fields for a `Client`. Any of them may be
+/// left unset, to be filled in by another source with `overlay`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ClientConfig {
+    pub api_url: Option<String>,
+    pub client_id: Option<String>,
+    pub client_key: Option<String>,
+
+    /// RequestInfo fields sent with every query, unless the query or the client's location
+    /// provider sets them
+    #[serde(default)]
+    pub request_info: Map<String, Value>,
+}
+
+impl ClientConfig {
+    /// Read `HOUNDIFY_CLIENT_ID`, `HOUNDIFY_CLIENT_KEY` and `HOUNDIFY_API_URL`. Variables
+    /// that are set but empty are treated as unset.
+    pub fn from_env() -> Self {
+        Self::from_lookup(|name| env::var(name).ok())
+    }
+
+    fn from_lookup<F: Fn(&str) -> Option<String>>(lookup: F) -> Self {
+        let lookup = |name| lookup(name).filter(|v: &String| !v.is_empty());
+        ClientConfig {
+            api_url: lookup(ENV_API_URL),
+            client_id: lookup(ENV_CLIENT_ID),
+            client_key: lookup(ENV_CLIENT_KEY),
+            request_info: Map::new(),
+        }
+    }
+
+    /// Combine with `other`, whose settings and RequestInfo fields take precedence. Object
+    /// fields of the RequestInfo are merged recursively.
+    pub fn overlay(mut self, other: ClientConfig) -> Self {
+        self.api_url = other.api_url.or(self.api_url);
+        self.client_id = other.client_id.or(self.client_id);
+        self.client_key = other.client_key.or(self.client_key);
+        let mut request_info = other.request_info;
+        merge_map(&mut request_info, &self.request_info);
+        self.request_info = request_info;
+        self
+    }
+
+    /// Create a client, failing with a `ConfigError` if the client ID or key is missing.
+    /// The API URL defaults to `DEFAULT_API_ENDPOINT`, and a trailing `/` is added if missing.
+    pub fn client(&self) -> Result<Client> {
+        let client_id = self
+            .client_id
+            .as_ref()
+            .ok_or_else(|| config_error("client_id is not set"))?;
+        let client_key = self
+            .client_key
+            .as_ref()
+            .ok_or_else(|| config_error("client_key is not set"))?;
+        let mut api_url = self
+            .api_url
+            .clone()
+            .unwrap_or_else(|| DEFAULT_API_ENDPOINT.to_string());
+        if !api_url.ends_with('/') {
+            api_url.push('/');
+        }
+        let mut client = Client::new(&api_url, client_id, client_key, None);
+        client.set_default_request_info(RequestInfo::from(self.request_info.clone()));
+        Ok(client)
+    }
+}
+
+/// A TOML config file. Top level settings apply to every profile, and each profile under
+/// `[profiles.<name>]` overrides them:
+///
+/// ```toml
+/// client_id = "..."
+/// client_key = "..."
+/// default_profile = "dev"
+///
+/// [request_info]
+/// InputLanguageIETFTag = "en-US"
+///
+/// [profiles.dev]
+/// api_url = "https://dev.example.com/"
+///
+/// [profiles.prod]
+/// api_url = "https://api.houndify.com/"
+/// ```
+///
+/// Unknown settings are an error, so that a misspelled key is not silently ignored.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(from = "ConfigFileFields")]
+pub struct ConfigFile {
+    /// Profile used when none is requested
+    pub default_profile: Option<String>,
+
+    #[serde(flatten)]
+    pub base: ClientConfig,
+
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ClientConfig>,
+}
+
+/// The fields of a `ConfigFile`, spelled out because serde cannot deny unknown fields next to
+/// a flattened struct
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFileFields {
+    default_profile: Option<String>,
+    api_url: Option<String>,
+    client_id: Option<String>,
+    client_key: Option<String>,
+    #[serde(default)]
+    request_info: Map<String, Value>,
+    #[serde(default)]
+    profiles: BTreeMap<String, ClientConfig>,
+}
+
+impl From<ConfigFileFields> for ConfigFile {
+    fn from(fields: ConfigFileFields) -> Self {
+        ConfigFile {
+            default_profile: fields.default_profile,
+            base: ClientConfig {
+                api_url: fields.api_url,
+                client_id: fields.client_id,
+                client_key: fields.client_key,
+                request_info: fields.request_info,
+            },
+            profiles: fields.profiles,
+        }
+    }
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<ConfigFile> {
+        let contents = std::fs::read_to_string(path).map_err(|e| HoundifyError::new(e.into()))?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<ConfigFile> {
+        toml::from_str(contents).map_err(|e| HoundifyError::new(e.into()))
+    }
+
+    /// The top level settings overlaid with those of `name`, or of the default profile if
+    /// `name` is `None`. Fails with a `ConfigError` if the profile does not exist.
+    pub fn profile(&self, name: Option<&str>) -> Result<ClientConfig> {
+        let name = match name.or(self.default_profile.as_deref()) {
+            Some(name) => name,
+            None => return Ok(self.base.clone()),
+        };
+        match self.profiles.get(name) {
+            Some(profile) => Ok(self.base.clone().overlay(profile.clone())),
+            None => Err(config_error(&format!("no profile named {:?}", name))),
+        }
+    }
+}
+
+impl Client {
+    /// Create a client from the `HOUNDIFY_CLIENT_ID`, `HOUNDIFY_CLIENT_KEY` and optional
+    /// `HOUNDIFY_API_URL` environment variables
+    pub fn from_env() -> Result<Client> {
+        ClientConfig::from_env().client()
+    }
+
+    /// Create a client from a TOML config file, using the profile named by `HOUNDIFY_PROFILE`
+    /// or else the file's default profile. The `HOUNDIFY_*` environment variables take
+    /// precedence over the file, unless they are empty.
+    pub fn from_config(path: &Path) -> Result<Client> {
+        let profile = env::var(ENV_PROFILE).ok().filter(|p| !p.is_empty());
+        Self::from_config_profile(path, profile.as_deref())
+    }
+
+    /// Like `from_config`, using the given profile
+    pub fn from_config_profile(path: &Path, profile: Option<&str>) -> Result<Client> {
+        ConfigFile::load(path)?
+            .profile(profile)?
+            .overlay(ClientConfig::from_env())
+            .client()
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+    use crate::location::{Location, StaticLocationProvider};
+    use crate::query::TextQuery;
+    use crate::test_server;
+    use serde_json::json;
+
+    static CONFIG: &str = r#"
+client_id = "client"
+client_key = "a2V5"
+default_profile = "dev"
+
+[request_info]
+InputLanguageIETFTag = "en-US"
+UnitPreference = "METRIC"
+
+[profiles.dev]
+api_url = "http://localhost:8080/"
+
+[profiles.prod]
+client_key = "cHJvZA=="
+
+[profiles.prod.request_info]
+UnitPreference = "US"
+"#;
+
+    #[test]
+    fn test_profiles() {
+        let file = ConfigFile::parse(CONFIG).unwrap();
+        let dev = file.profile(None).unwrap();
+        assert_eq!(dev.api_url.as_deref(), Some("http://localhost:8080/"));
+        assert_eq!(dev.client_key.as_deref(), Some("a2V5"));
+
+        let prod = file.profile(Some("prod")).unwrap();
+        assert_eq!(prod.api_url, None);
+        assert_eq!(prod.client_id.as_deref(), Some("client"));
+        assert_eq!(prod.client_key.as_deref(), Some("cHJvZA=="));
+        assert_eq!(
+            Value::Object(prod.request_info),
+            json!({"InputLanguageIETFTag": "en-US", "UnitPreference": "US"})
+        );
+
+        let err = file.profile(Some("staging")).unwrap_err();
+        assert!(err.downcast_ref::<ConfigError>().is_some());
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        assert!(ConfigFile::parse("client_id = \"client\"\nclient_kye = \"a2V5\"").is_err());
+        assert!(ConfigFile::parse("[profiles.dev]\napi_ulr = \"http://localhost/\"").is_err());
+        let file = ConfigFile::parse(CONFIG).unwrap();
+        assert_eq!(
+            ConfigFile::parse(&toml::to_string(&file).unwrap()).unwrap(),
+            file
+        );
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let file = ConfigFile::parse(CONFIG).unwrap();
+        let env = ClientConfig::from_lookup(|name| match name {
+            "HOUNDIFY_CLIENT_ID" => Some("env-client".to_string()),
+            _ => None,
+        });
+        let config = file.profile(None).unwrap().overlay(env);
+        assert_eq!(config.client_id.as_deref(), Some("env-client"));
+        assert_eq!(config.client_key.as_deref(), Some("a2V5"));
+
+        let empty = ClientConfig::from_lookup(|_| Some(String::new()));
+        assert_eq!(empty, ClientConfig::default());
+        let config = file.profile(None).unwrap().overlay(empty);
+        assert_eq!(config.api_url.as_deref(), Some("http://localhost:8080/"));
+
+        let err = ClientConfig::from_lookup(|_| None).client().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ConfigError>().unwrap().message(),
+            "client_id is not set"
+        );
+    }
+
+    #[test]
+    fn test_overlay_merges_request_info() {
+        let config = |request_info: Value| ClientConfig {
+            request_info: request_info.as_object().unwrap().clone(),
+            ..ClientConfig::default()
+        };
+        let base = config(json!({
+            "UnitPreference": "METRIC",
+            "ClientMetadata": {"AppVersion": "1.0", "Build": 7}
+        }));
+        let profile = config(json!({"ClientMetadata": {"AppVersion": "2.0"}}));
+        assert_eq!(
+            Value::Object(base.overlay(profile).request_info),
+            json!({
+                "UnitPreference": "METRIC",
+                "ClientMetadata": {"AppVersion": "2.0", "Build": 7}
+            })
+        );
+    }
+
+    #[test]
+    fn test_api_url_without_trailing_slash() {
+        let url = test_server::serve(|_| test_server::ok_response("2", "Arithmetic", 0.5));
+        let client = ClientConfig::from_lookup(|name| match name {
+            "HOUNDIFY_API_URL" => Some(url.trim_end_matches('/').to_string()),
+            "HOUNDIFY_CLIENT_ID" => Some("client".to_string()),
+            "HOUNDIFY_CLIENT_KEY" => Some("a2V5".to_string()),
+            _ => None,
+        })
+        .client()
+        .unwrap();
+        let query = TextQuery::new("one plus one", "user", RequestInfo::new());
+        assert!(client.text_query(query).is_ok());
+    }
+
+    #[test]
+    fn test_default_request_info() {
+        let url = test_server::serve(|request| {
+            let request_info: Value =
+                serde_json::from_str(&request.headers["houndify-request-info"]).unwrap();
+            assert_eq!(request_info["InputLanguageIETFTag"], "en-US");
+            assert_eq!(request_info["UnitPreference"], "US");
+            test_server::ok_response("2", "Arithmetic", 0.5)
+        });
+        let mut config = ConfigFile::parse(CONFIG).unwrap().profile(None).unwrap();
+        config.api_url = Some(url);
+        let client = config.client().unwrap();
+
+        let mut request_info = RequestInfo::new();
+        request_info.set("UnitPreference".to_string(), json!("US"));
+        let response = client.text_query(TextQuery::new("one plus one", "user", request_info));
+        assert!(response.is_ok());
+    }
+
+    #[test]
+    fn test_location_provider_overrides_defaults() {
+        let url = test_server::serve(|request| {
+            let request_info: Value =
+                serde_json::from_str(&request.headers["houndify-request-info"]).unwrap();
+            assert_eq!(request_info["Latitude"], 43.65);
+            assert_eq!(request_info["Longitude"], -79.38);
+            test_server::ok_response("2", "Arithmetic", 0.5)
+        });
+        let config = format!(
+            "api_url = {:?}\nclient_id = \"client\"\nclient_key = \"a2V5\"\n\n\
+             [request_info]\nLatitude = 37.77\nLongitude = -122.42\n",
+            url
+        );
+        let mut client = ConfigFile::parse(&config)
+            .unwrap()
+            .profile(None)
+            .unwrap()
+            .client()
+            .unwrap();
+        client.set_location_provider(StaticLocationProvider::new(Location::new(43.65, -79.38)));
+        let query = TextQuery::new("one plus one", "user", RequestInfo::new());
+        assert!(client.text_query(query).is_ok());
+    }
+}
@@ -0,0 +1,295 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Reference: https://docs.houndify.com/reference/MapLocation
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct MapLocation {
+    pub r#type: Option<String>,
+    pub label: Option<String>,
+    pub spoken_label: Option<String>,
+    pub address: Option<String>,
+    pub city: Option<String>,
+    pub admin1: Option<String>,
+    pub country: Option<String>,
+    pub country_code: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub time_zone: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Date {
+    pub year: i64,
+    pub month: i64,
+    pub day_of_month: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Time {
+    pub hour: i64,
+    pub minute: i64,
+    pub second: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct DateAndTime {
+    pub date: Option<Date>,
+    pub time: Option<Time>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct DateTimeSpec {
+    pub date_and_time: Option<DateAndTime>,
+}
+
+/// The `DateAndTime` information nugget
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct TimeResult {
+    pub date_and_time_nugget_kind: String,
+    pub source_date_time_spec: Option<DateTimeSpec>,
+    pub destination_map_location: Option<MapLocation>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Temperature {
+    pub value: f64,
+    pub unit: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct WeatherConditions {
+    pub conditions_description: Option<String>,
+    pub temperature: Option<Temperature>,
+    pub humidity: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct WeatherResult {
+    pub weather_nugget_kind: Option<String>,
+    pub map_location: Option<MapLocation>,
+    pub current_conditions: WeatherConditions,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct CalculatorResult {
+    pub expression: String,
+    pub result: Value,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Track {
+    #[serde(rename = "TrackID")]
+    pub track_id: Option<Value>,
+    pub track_name: Option<String>,
+    pub artist_name: Option<String>,
+    pub album_name: Option<String>,
+    pub album_date: Option<String>,
+}
+
+/// Reference: https://docs.houndify.com/reference/MusicSearchCommand
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct MusicSearchResult {
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct MapResult {
+    pub map_location: MapLocation,
+    pub destination_map_location: Option<MapLocation>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Game {
+    pub home_team: Option<String>,
+    pub away_team: Option<String>,
+    pub home_score: Option<i64>,
+    pub away_score: Option<i64>,
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct SportsResult {
+    pub league: Option<String>,
+    pub games: Vec<Game>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct StockResult {
+    pub symbol: String,
+    pub company_name: Option<String>,
+    pub current_price: Option<f64>,
+    pub change: Option<f64>,
+    pub change_percent: Option<f64>,
+    pub currency: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Definition {
+    pub part_of_speech: Option<String>,
+    pub definition: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct DictionaryResult {
+    pub word: String,
+    pub definitions: Vec<Definition>,
+}
+
+/// A typed view of a single entry of `AllResults`
+///
+/// Each model requires the fields that identify its domain, so data of another shape falls
+/// back to `Unknown` rather than decoding as an empty model.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DomainResult {
+    Weather(WeatherResult),
+    Calculator(CalculatorResult),
+    Time(TimeResult),
+    MusicSearch(MusicSearchResult),
+    Map(MapResult),
+    Sports(SportsResult),
+    Stocks(StockResult),
+    Dictionary(DictionaryResult),
+    /// The `CommandKind` is not modelled, or the data did not match the model
+    Unknown(Map<String, Value>),
+}
+
+impl DomainResult {
+    /// Decode a single entry of `AllResults`.
+    ///
+    /// The entry is dispatched on its `CommandKind`. `InformationCommand`s are further
+    /// dispatched on the `NuggetKind` of their first information nugget.
+    pub fn from_result(result: &Map<String, Value>) -> DomainResult {
+        let command_kind = result.get("CommandKind").and_then(Value::as_str);
+        let decoded = match command_kind {
+            Some("InformationCommand") => Self::from_information_nugget(result),
+            Some("WeatherCommand") => decode(native_data(result)).map(DomainResult::Weather),
+            Some("CalculatorCommand") => decode(native_data(result)).map(DomainResult::Calculator),
+            Some("MusicSearchCommand") => {
+                decode(native_data(result)).map(DomainResult::MusicSearch)
+            }
+            Some("MapCommand") | Some("NavigationCommand") => {
+                decode(native_data(result)).map(DomainResult::Map)
+            }
+            Some("SportsCommand") => decode(native_data(result)).map(DomainResult::Sports),
+            Some("StockMarketCommand") => decode(native_data(result)).map(DomainResult::Stocks),
+            Some("DictionaryCommand") => decode(native_data(result)).map(DomainResult::Dictionary),
+            _ => None,
+        };
+        decoded.unwrap_or_else(|| DomainResult::Unknown(result.clone()))
+    }
+
+    fn from_information_nugget(result: &Map<String, Value>) -> Option<DomainResult> {
+        let nugget = result
+            .get("InformationNuggets")
+            .and_then(Value::as_array)
+            .and_then(|nuggets| nuggets.first())?;
+        match nugget.get("NuggetKind").and_then(Value::as_str) {
+            Some("DateAndTime") => decode(nugget.clone()).map(DomainResult::Time),
+            Some("Weather") => decode(nugget.clone()).map(DomainResult::Weather),
+            Some("Calculator") => decode(nugget.clone()).map(DomainResult::Calculator),
+            Some("StockMarket") => decode(nugget.clone()).map(DomainResult::Stocks),
+            Some("Dictionary") => decode(nugget.clone()).map(DomainResult::Dictionary),
+            Some("Sports") => decode(nugget.clone()).map(DomainResult::Sports),
+            _ => None,
+        }
+    }
+}
+
+/// Domain specific data lives in `NativeData` when present, otherwise in the result itself
+fn native_data(result: &Map<String, Value>) -> Value {
+    match result.get("NativeData") {
+        Some(v @ Value::Object(_)) => v.clone(),
+        _ => Value::Object(result.clone()),
+    }
+}
+
+fn decode<T: DeserializeOwned>(v: Value) -> Option<T> {
+    serde_json::from_value(v).ok()
+}
+
+#[cfg(test)]
+mod domains_tests {
+    use super::*;
+    use crate::response::HoundServerResponse;
+    use serde_json::json;
+
+    #[test]
+    fn test_time_result() {
+        let file = std::fs::File::open("tests/sample_result.json").unwrap();
+        let hsr: HoundServerResponse = serde_json::from_reader(file).unwrap();
+        let result = &hsr.all_results.unwrap()[0];
+        match DomainResult::from_result(result) {
+            DomainResult::Time(t) => {
+                assert_eq!(t.date_and_time_nugget_kind, "TimeInLocation");
+                let location = t.destination_map_location.unwrap();
+                assert_eq!(location.city.unwrap(), "Toronto");
+            }
+            other => panic!("Unexpected domain result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_music_search_result() {
+        let result = json!({
+            "CommandKind": "MusicSearchCommand",
+            "NativeData": {
+                "Tracks": [{"TrackID": 200, "TrackName": "Yesterday", "ArtistName": "The Beatles"}]
+            }
+        });
+        match DomainResult::from_result(result.as_object().unwrap()) {
+            DomainResult::MusicSearch(m) => {
+                assert_eq!(m.tracks[0].track_name.as_deref(), Some("Yesterday"))
+            }
+            other => panic!("Unexpected domain result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unknown_result() {
+        let result = json!({"CommandKind": "AlarmCommand", "AlarmCommandKind": "SetAlarm"});
+        let map = result.as_object().unwrap();
+        assert_eq!(
+            DomainResult::from_result(map),
+            DomainResult::Unknown(map.clone())
+        );
+    }
+
+    #[test]
+    fn test_mismatched_result() {
+        let results = vec![
+            json!({"CommandKind": "WeatherCommand", "NativeData": {"Tracks": []}}),
+            json!({"CommandKind": "MusicSearchCommand", "NativeData": {"Symbol": "SOUN"}}),
+            json!({"CommandKind": "StockMarketCommand"}),
+            json!({
+                "CommandKind": "InformationCommand",
+                "InformationNuggets": [{"NuggetKind": "Calculator", "Expression": "1 + 1"}]
+            }),
+        ];
+        for result in results {
+            let map = result.as_object().unwrap();
+            assert_eq!(
+                DomainResult::from_result(map),
+                DomainResult::Unknown(map.clone())
+            );
+        }
+    }
+}
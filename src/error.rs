@@ -1,9 +1,10 @@
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct HoundifyError {
-    inner: Box<dyn Error>,
+    inner: Box<dyn Error + Send + Sync>,
 }
 
 impl Display for HoundifyError {
@@ -19,9 +20,14 @@ impl Display for HoundifyError {
 impl Error for HoundifyError {}
 
 impl HoundifyError {
-    pub fn new(inner: Box<dyn Error>) -> Self {
+    pub fn new(inner: Box<dyn Error + Send + Sync>) -> Self {
         HoundifyError { inner }
     }
+
+    /// The underlying error, if it is of type `T`
+    pub fn downcast_ref<T: Error + 'static>(&self) -> Option<&T> {
+        self.inner.downcast_ref::<T>()
+    }
 }
 
 #[derive(Debug)]
@@ -40,3 +46,184 @@ impl<'a> Display for InvalidRequestInfoError<'a> {
         f.write_str(self.msg)
     }
 }
+
+/// Returned when a client match `Expression` cannot be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidExpressionError {
+    position: usize,
+    msg: String,
+}
+
+impl InvalidExpressionError {
+    pub fn new(position: usize, msg: &str) -> Self {
+        InvalidExpressionError {
+            position,
+            msg: msg.to_string(),
+        }
+    }
+
+    /// Character offset into the expression where the error was detected
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+}
+
+impl Display for InvalidExpressionError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{} at position {}", self.msg, self.position)
+    }
+}
+
+impl Error for InvalidExpressionError {}
+
+/// Returned instead of sending a query once the credit budget for the current window is spent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetExceededError {
+    spent: f64,
+    budget: f64,
+}
+
+impl BudgetExceededError {
+    pub fn new(spent: f64, budget: f64) -> Self {
+        BudgetExceededError { spent, budget }
+    }
+
+    /// Credits used within the current window
+    pub fn spent(&self) -> f64 {
+        self.spent
+    }
+
+    pub fn budget(&self) -> f64 {
+        self.budget
+    }
+}
+
+impl Display for BudgetExceededError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(
+            f,
+            "Credit budget exceeded: {} of {} credits used",
+            self.spent, self.budget
+        )
+    }
+}
+
+impl Error for BudgetExceededError {}
+
+/// Returned by the non-blocking queries when the rate limiter has no token available.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitedError {
+    wait: Duration,
+}
+
+impl RateLimitedError {
+    pub fn new(wait: Duration) -> Self {
+        RateLimitedError { wait }
+    }
+
+    /// How long until the query would be allowed
+    pub fn wait(&self) -> Duration {
+        self.wait
+    }
+}
+
+impl Display for RateLimitedError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "Rate limited, retry in {:?}", self.wait)
+    }
+}
+
+impl Error for RateLimitedError {}
+
+/// Returned when a client cannot be configured from the environment or a config file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    msg: String,
+}
+
+impl ConfigError {
+    pub fn new(msg: &str) -> Self {
+        ConfigError {
+            msg: msg.to_string(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "Invalid configuration: {}", self.msg)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Returned when a response is not in the format this SDK decodes, or in a version of it the
+/// `FormatVersionPolicy` rejects.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsupportedFormatError {
+    format: String,
+    format_version: String,
+}
+
+impl UnsupportedFormatError {
+    pub fn new(format: &str, format_version: &str) -> Self {
+        UnsupportedFormatError {
+            format: format.to_string(),
+            format_version: format_version.to_string(),
+        }
+    }
+
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    pub fn format_version(&self) -> &str {
+        &self.format_version
+    }
+}
+
+impl Display for UnsupportedFormatError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(
+            f,
+            "Unsupported response format {} version {}",
+            self.format, self.format_version
+        )
+    }
+}
+
+impl Error for UnsupportedFormatError {}
+
+/// Returned when an audio file is in a format `VoiceQuery::from_file` cannot send or transcode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsupportedAudioError {
+    msg: String,
+}
+
+impl UnsupportedAudioError {
+    pub fn new(msg: &str) -> Self {
+        UnsupportedAudioError {
+            msg: msg.to_string(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+}
+
+impl Display for UnsupportedAudioError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "Unsupported audio: {}", self.msg)
+    }
+}
+
+impl Error for UnsupportedAudioError {}
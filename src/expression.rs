@@ -0,0 +1,391 @@
+use crate::error::InvalidExpressionError;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A parsed client match `Expression`.
+///
+/// The syntax follows the Houndify expression language:
+///
+/// * `"turn on"` - a literal term
+/// * `a . b` - `a` followed by `b`
+/// * `a | b` - either `a` or `b` (binds looser than `.`)
+/// * `[a]` - `a` is optional
+/// * `(a)` - grouping
+///
+/// Reference: https://docs.houndify.com/reference/ClientMatch
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Term(String),
+    Sequence(Vec<Expression>),
+    Alternatives(Vec<Expression>),
+    Optional(Box<Expression>),
+}
+
+impl Expression {
+    /// Parse and validate an expression string
+    pub fn parse(s: &str) -> std::result::Result<Expression, InvalidExpressionError> {
+        let mut parser = Parser {
+            chars: s.chars().collect(),
+            pos: 0,
+        };
+        let expr = parser.alternatives()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(expr),
+            Some(')') => Err(parser.error("Unmatched ')'")),
+            Some(']') => Err(parser.error("Unmatched ']'")),
+            Some(_) => Err(parser.error("Expected '.' or '|' between terms")),
+        }
+    }
+
+    /// Enumerate up to `limit` phrases matched by this expression, in the order they appear in
+    /// the expression.
+    pub fn sample_phrases(&self, limit: usize) -> Vec<String> {
+        self.expand(limit)
+            .into_iter()
+            .map(|words| words.join(" "))
+            .collect()
+    }
+
+    /// Whether `phrase` is matched by this expression. Whitespace and case are ignored.
+    pub fn matches(&self, phrase: &str) -> bool {
+        let words: Vec<String> = phrase.split_whitespace().map(str::to_lowercase).collect();
+        self.match_at(&words, 0).contains(&words.len())
+    }
+
+    fn expand(&self, limit: usize) -> Vec<Vec<String>> {
+        match self {
+            Expression::Term(_) if limit == 0 => vec![],
+            Expression::Term(t) => vec![t.split_whitespace().map(String::from).collect()],
+            Expression::Optional(inner) => {
+                let mut out = vec![vec![]];
+                out.extend(inner.expand(limit.saturating_sub(1)));
+                out.truncate(limit);
+                out
+            }
+            Expression::Alternatives(alts) => {
+                let mut out = vec![];
+                for alt in alts {
+                    if out.len() >= limit {
+                        break;
+                    }
+                    out.extend(alt.expand(limit - out.len()));
+                }
+                out
+            }
+            Expression::Sequence(items) => {
+                let mut out: Vec<Vec<String>> = vec![vec![]];
+                for item in items {
+                    let tails = item.expand(limit);
+                    let mut next = vec![];
+                    'outer: for head in &out {
+                        for tail in &tails {
+                            if next.len() >= limit {
+                                break 'outer;
+                            }
+                            let mut words = head.clone();
+                            words.extend(tail.iter().cloned());
+                            next.push(words);
+                        }
+                    }
+                    out = next;
+                }
+                out
+            }
+        }
+    }
+
+    /// Returns every word index at which a match starting at `start` can end
+    fn match_at(&self, words: &[String], start: usize) -> Vec<usize> {
+        match self {
+            Expression::Term(t) => {
+                let mut end = start;
+                for w in t.split_whitespace() {
+                    match words.get(end) {
+                        Some(word) if *word == w.to_lowercase() => end += 1,
+                        _ => return vec![],
+                    }
+                }
+                vec![end]
+            }
+            Expression::Optional(inner) => {
+                let mut ends = vec![start];
+                ends.extend(inner.match_at(words, start));
+                ends
+            }
+            Expression::Alternatives(alts) => alts
+                .iter()
+                .flat_map(|alt| alt.match_at(words, start))
+                .collect(),
+            Expression::Sequence(items) => {
+                let mut ends = vec![start];
+                for item in items {
+                    let mut next: Vec<usize> =
+                        ends.iter().flat_map(|&s| item.match_at(words, s)).collect();
+                    next.sort_unstable();
+                    next.dedup();
+                    ends = next;
+                }
+                ends
+            }
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = InvalidExpressionError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Expression::parse(s)
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Expression::Term(t) => {
+                write!(f, "\"{}\"", t.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+            Expression::Optional(inner) => write!(f, "[{}]", inner),
+            Expression::Sequence(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" . ")?;
+                    }
+                    match item {
+                        Expression::Alternatives(_) => write!(f, "({})", item)?,
+                        _ => write!(f, "{}", item)?,
+                    }
+                }
+                Ok(())
+            }
+            Expression::Alternatives(alts) => {
+                for (i, alt) in alts.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" | ")?;
+                    }
+                    write!(f, "{}", alt)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+type ParseResult = std::result::Result<Expression, InvalidExpressionError>;
+
+impl Parser {
+    fn error(&self, msg: &str) -> InvalidExpressionError {
+        InvalidExpressionError::new(self.pos, msg)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn alternatives(&mut self) -> ParseResult {
+        let mut alts = vec![self.sequence()?];
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('|') {
+                break;
+            }
+            self.pos += 1;
+            alts.push(self.sequence()?);
+        }
+        if alts.len() == 1 {
+            Ok(alts.remove(0))
+        } else {
+            Ok(Expression::Alternatives(alts))
+        }
+    }
+
+    fn sequence(&mut self) -> ParseResult {
+        let mut items = vec![self.atom()?];
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('.') {
+                break;
+            }
+            self.pos += 1;
+            items.push(self.atom()?);
+        }
+        if items.len() == 1 {
+            Ok(items.remove(0))
+        } else {
+            Ok(Expression::Sequence(items))
+        }
+    }
+
+    fn atom(&mut self) -> ParseResult {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"') => self.term(),
+            Some('(') => self.group(')'),
+            Some('[') => {
+                let inner = self.group(']')?;
+                Ok(Expression::Optional(Box::new(inner)))
+            }
+            Some(c @ '|') | Some(c @ '.') | Some(c @ ')') | Some(c @ ']') => {
+                Err(self.error(&format!("Expected a term before '{}'", c)))
+            }
+            Some(c) => Err(self.error(&format!("Unexpected character '{}'", c))),
+            None => Err(self.error("Unexpected end of expression")),
+        }
+    }
+
+    fn group(&mut self, close: char) -> ParseResult {
+        let open = self.pos;
+        self.pos += 1;
+        let inner = self.alternatives()?;
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == close => {
+                self.pos += 1;
+                Ok(inner)
+            }
+            Some(')') | Some(']') => Err(self.error("Mismatched closing bracket")),
+            Some(_) => Err(self.error("Expected '.' or '|' between terms")),
+            None => Err(InvalidExpressionError::new(
+                open,
+                &format!("Unclosed '{}'", self.chars[open]),
+            )),
+        }
+    }
+
+    fn term(&mut self) -> ParseResult {
+        let open = self.pos;
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(c) => text.push(c),
+                        None => {
+                            return Err(InvalidExpressionError::new(
+                                open,
+                                "Unterminated string literal",
+                            ))
+                        }
+                    }
+                    self.pos += 1;
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.pos += 1;
+                }
+                None => {
+                    return Err(InvalidExpressionError::new(
+                        open,
+                        "Unterminated string literal",
+                    ))
+                }
+            }
+        }
+        if text.trim().is_empty() {
+            return Err(InvalidExpressionError::new(open, "Empty term"));
+        }
+        Ok(Expression::Term(text))
+    }
+}
+
+#[cfg(test)]
+mod expression_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_precedence() {
+        let expr = Expression::parse(r#""turn" . "on" | "off""#).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Alternatives(vec![
+                Expression::Sequence(vec![
+                    Expression::Term("turn".to_string()),
+                    Expression::Term("on".to_string()),
+                ]),
+                Expression::Term("off".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_sample_phrases() {
+        let expr: Expression = r#"("turn" | "switch") . "on" . ["the"] . "lights""#
+            .parse()
+            .unwrap();
+        assert_eq!(
+            expr.sample_phrases(10),
+            vec![
+                "turn on lights",
+                "turn on the lights",
+                "switch on lights",
+                "switch on the lights",
+            ]
+        );
+        assert_eq!(expr.sample_phrases(2).len(), 2);
+        assert!(expr.sample_phrases(0).is_empty());
+        assert!(Expression::Term("on".to_string())
+            .sample_phrases(0)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_matches() {
+        let expr = Expression::parse(r#"["please"] . "turn on" . ["the"] . "lights""#).unwrap();
+        assert!(expr.matches("Turn on the lights"));
+        assert!(expr.matches("please turn on lights"));
+        assert!(!expr.matches("turn off the lights"));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let expr = Expression::parse(r#"("a" | "b") . ["c" . "d"]"#).unwrap();
+        assert_eq!(Expression::parse(&expr.to_string()).unwrap(), expr);
+
+        let escaped = Expression::parse(r#""a\\b" . "say \"hi\"""#).unwrap();
+        assert_eq!(
+            escaped,
+            Expression::Sequence(vec![
+                Expression::Term("a\\b".to_string()),
+                Expression::Term("say \"hi\"".to_string()),
+            ])
+        );
+        assert_eq!(Expression::parse(&escaped.to_string()).unwrap(), escaped);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = vec![
+            (r#""turn" "on""#, 7),
+            (r#"("turn" . "on""#, 0),
+            (r#""turn . "on""#, 9),
+            (r#""turn" . | "on""#, 9),
+            (r#"["turn")"#, 7),
+            (r#""turn" . """#, 9),
+            (r#""turn" ."#, 8),
+            (r#"turn"#, 0),
+            (r#""turn\"#, 0),
+        ];
+        for (expr, position) in cases {
+            let err = Expression::parse(expr).unwrap_err();
+            assert_eq!(err.position(), position, "{}: {}", expr, err);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::error::UnsupportedFormatError;
+use crate::response::HoundServerResponse;
+use std::fmt::{self, Debug, Formatter};
+
+/// The "Format" of every response this SDK decodes
+pub static RESPONSE_FORMAT: &str = "SoundHoundVoiceSearchResult";
+
+/// The major "FormatVersion" this SDK decodes. Minor versions only add fields, which are kept
+/// in `HoundServerResponse::extra`.
+pub static SUPPORTED_FORMAT_MAJOR_VERSION: u64 = 1;
+
+/// JSON Schema (draft 7) of `HoundServerResponse`, for validating payloads outside this SDK.
+/// Its entries of "AllResults" refer by `$id` to `COMMAND_RESULT_SCHEMA`, so a validator must
+/// be given that schema and `TEMPLATE_DATA_SCHEMA` as well.
+pub static HOUND_SERVER_RESPONSE_SCHEMA: &str =
+    include_str!("../schemas/hound_server_response.schema.json");
+
+/// JSON Schema (draft 7) of an entry of "AllResults". Its "TemplateData" refers by `$id` to
+/// `TEMPLATE_DATA_SCHEMA`.
+pub static COMMAND_RESULT_SCHEMA: &str = include_str!("../schemas/command_result.schema.json");
+
+/// JSON Schema (draft 7) of `TemplateData`
+pub static TEMPLATE_DATA_SCHEMA: &str = include_str!("../schemas/template_data.schema.json");
+
+/// Called with each response whose "FormatVersion" is not supported, under
+/// `FormatVersionPolicy::Warn`
+pub type FormatWarningCallback = Box<dyn Fn(&UnsupportedFormatError) + Send + Sync>;
+
+/// Whether `format_version` has the supported major version
+pub fn is_supported_format_version(format_version: &str) -> bool {
+    format_version
+        .split('.')
+        .next()
+        .and_then(|major| major.trim().parse::<u64>().ok())
+        == Some(SUPPORTED_FORMAT_MAJOR_VERSION)
+}
+
+/// What to do with a response whose "FormatVersion" is not supported. Responses whose "Format"
+/// is not `RESPONSE_FORMAT` are always rejected.
+pub enum FormatVersionPolicy {
+    /// Decode the response as usual
+    Accept,
+    /// Decode the response as usual, after calling the callback
+    Warn(FormatWarningCallback),
+    /// Fail the query with an `UnsupportedFormatError`
+    Reject,
+}
+
+impl FormatVersionPolicy {
+    pub fn warn<F>(f: F) -> Self
+    where
+        F: Fn(&UnsupportedFormatError) + Send + Sync + 'static,
+    {
+        FormatVersionPolicy::Warn(Box::new(f))
+    }
+
+    /// Check the "Format" and "FormatVersion" of `response` against this policy
+    pub fn check(&self, response: &HoundServerResponse) -> Result<(), UnsupportedFormatError> {
+        let error = || UnsupportedFormatError::new(&response.format, &response.format_version);
+        if response.format != RESPONSE_FORMAT {
+            return Err(error());
+        }
+        if is_supported_format_version(&response.format_version) {
+            return Ok(());
+        }
+        match self {
+            FormatVersionPolicy::Accept => Ok(()),
+            FormatVersionPolicy::Warn(f) => {
+                f(&error());
+                Ok(())
+            }
+            FormatVersionPolicy::Reject => Err(error()),
+        }
+    }
+}
+
+/// Logs a warning through the `log` crate, which is silent unless the application installs a
+/// logger
+impl Default for FormatVersionPolicy {
+    fn default() -> Self {
+        FormatVersionPolicy::warn(|e| log::warn!("{}", e))
+    }
+}
+
+impl Debug for FormatVersionPolicy {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FormatVersionPolicy::Accept => f.write_str("Accept"),
+            FormatVersionPolicy::Warn(_) => f.write_str("Warn"),
+            FormatVersionPolicy::Reject => f.write_str("Reject"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod format_tests {
+    use super::*;
+    use crate::response::TemplateData;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn response(format: &str, format_version: &str) -> HoundServerResponse {
+        serde_json::from_value(json!({
+            "Format": format,
+            "FormatVersion": format_version,
+            "Status": "OK",
+            "QueryID": "deadbeef",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_format_versions() {
+        assert!(is_supported_format_version("1.0"));
+        assert!(is_supported_format_version("1.3"));
+        assert!(!is_supported_format_version("2.0"));
+        assert!(!is_supported_format_version("beta"));
+    }
+
+    #[test]
+    fn test_policies() {
+        let current = response(RESPONSE_FORMAT, "1.0");
+        let future = response(RESPONSE_FORMAT, "2.0");
+        assert!(FormatVersionPolicy::Reject.check(&current).is_ok());
+        assert!(FormatVersionPolicy::Accept.check(&future).is_ok());
+        let err = FormatVersionPolicy::Reject.check(&future).unwrap_err();
+        assert_eq!(err.format_version(), "2.0");
+
+        let warnings = Arc::new(AtomicUsize::new(0));
+        let counter = warnings.clone();
+        let warn = FormatVersionPolicy::warn(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(warn.check(&current).is_ok());
+        assert!(warn.check(&future).is_ok());
+        assert_eq!(warnings.load(Ordering::SeqCst), 1);
+
+        let other = response("SomethingElse", "1.0");
+        assert!(FormatVersionPolicy::Accept.check(&other).is_err());
+    }
+
+    /// Compile `schema` with the schemas it may refer to
+    fn compile(schema: &str) -> jsonschema::JSONSchema {
+        let mut options = jsonschema::JSONSchema::options();
+        for document in &[COMMAND_RESULT_SCHEMA, TEMPLATE_DATA_SCHEMA] {
+            let document: Value = serde_json::from_str(document).unwrap();
+            let id = document["$id"].as_str().unwrap().to_string();
+            options.with_document(id, document);
+        }
+        options
+            .compile(&serde_json::from_str(schema).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_schema_validates_responses() {
+        let schema = compile(HOUND_SERVER_RESPONSE_SCHEMA);
+
+        let file = std::fs::File::open("tests/sample_result.json").unwrap();
+        let sample: Value = serde_json::from_reader(file).unwrap();
+        assert!(schema.is_valid(&sample));
+        let response: HoundServerResponse = serde_json::from_value(sample.clone()).unwrap();
+        assert!(schema.is_valid(&serde_json::to_value(&response).unwrap()));
+
+        let mut bad = sample;
+        bad["Format"] = json!("SomethingElse");
+        assert!(!schema.is_valid(&bad));
+        bad["Format"] = json!(RESPONSE_FORMAT);
+        bad["AllResults"][0]["TemplateData"] = json!({"TemplateName": "ImageTemplate"});
+        assert!(!schema.is_valid(&bad));
+        bad["AllResults"][0] = json!({"WrittenResponse": "2"});
+        assert!(!schema.is_valid(&bad));
+    }
+
+    #[test]
+    fn test_nested_schemas() {
+        let command_result = compile(COMMAND_RESULT_SCHEMA);
+        let file = std::fs::File::open("tests/sample_result.json").unwrap();
+        let sample: Value = serde_json::from_reader(file).unwrap();
+        let result = &sample["AllResults"][0];
+        assert!(command_result.is_valid(result));
+        assert!(!command_result.is_valid(&json!({"CommandKind": 7})));
+        assert!(!command_result.is_valid(&json!({
+            "CommandKind": "InformationCommand",
+            "TemplateData": {"TemplateName": "SimpleText"}
+        })));
+
+        let template_data = compile(TEMPLATE_DATA_SCHEMA);
+        let templates = vec![
+            TemplateData::from_value(&json!({"TemplateName": "SimpleText", "Title": "2"})),
+            TemplateData::from_value(&json!({
+                "TemplateName": "VerticalTemplateList",
+                "Items": [{"TemplateData": {"TemplateName": "ImageTemplate", "ImageURL": "a.png"}}]
+            })),
+            TemplateData::from_value(&json!({"TemplateName": "MapTemplate", "Zoom": 3})),
+        ];
+        for template in templates {
+            assert!(template_data.is_valid(&serde_json::to_value(&template).unwrap()));
+        }
+        assert!(!template_data.is_valid(&json!({
+            "TemplateName": "VerticalTemplateList",
+            "Items": [{"TemplateData": {"TemplateName": "ImageTemplate"}}]
+        })));
+    }
+}
@@ -0,0 +1,627 @@
+//! Regression tests for Houndify domains.
+//!
+//! A test suite is a JSON (or, with the `yaml` feature, YAML) file listing utterances and what
+//! the response to each is expected to contain:
+//!
+//! ```yaml
+//! name: arithmetic
+//! tests:
+//!   - name: one plus one
+//!     utterance: what is one plus one
+//!     expect:
+//!       command_kind: InformationCommand
+//!       written_response_contains: ["2"]
+//!       json_path:
+//!         - path: "$[0].SpokenResponse"
+//!           contains: "2"
+//! ```
+//!
+//! JSONPath expressions are evaluated against "AllResults" and support `$`, `.Field`,
+//! `['Field']`, `[n]` and `[*]`.
+
+use crate::client::Client;
+use crate::error::HoundifyError;
+use crate::query::{RequestInfo, TextQuery};
+use crate::response::HoundServerResponse;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+static DEFAULT_USER_ID: &str = "houndify-harness";
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TestSuite {
+    #[serde(default = "default_suite_name")]
+    pub name: String,
+    pub tests: Vec<TestCase>,
+}
+
+fn default_suite_name() -> String {
+    "houndify".to_string()
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TestCase {
+    pub name: Option<String>,
+    pub utterance: String,
+    pub user_id: Option<String>,
+    /// Extra RequestInfo fields sent with the query
+    #[serde(default)]
+    pub request_info: Map<String, Value>,
+    #[serde(default)]
+    pub expect: Expectation,
+}
+
+impl TestCase {
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.utterance)
+    }
+
+    /// The user ID the query is sent with
+    pub fn user_id(&self) -> &str {
+        self.user_id.as_deref().unwrap_or(DEFAULT_USER_ID)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Expectation {
+    /// Expected "CommandKind" of the first result
+    pub command_kind: Option<String>,
+    /// Substrings the first result's "WrittenResponse" must contain
+    #[serde(default)]
+    pub written_response_contains: Vec<String>,
+    /// Substrings the first result's "SpokenResponse" must contain
+    #[serde(default)]
+    pub spoken_response_contains: Vec<String>,
+    #[serde(default)]
+    pub json_path: Vec<JsonPathAssertion>,
+}
+
+/// An assertion on the values selected by a JSONPath expression. With no condition, the path
+/// must select at least one value.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct JsonPathAssertion {
+    pub path: String,
+    /// Some selected value must equal this
+    pub equals: Option<Value>,
+    /// Some selected string must contain this
+    pub contains: Option<String>,
+    /// Whether the path must select anything
+    pub exists: Option<bool>,
+}
+
+/// A single failed expectation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub message: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug)]
+pub struct CaseReport {
+    pub name: String,
+    pub utterance: String,
+    pub duration: Duration,
+    /// Set if the query itself failed
+    pub error: Option<String>,
+    pub failures: Vec<Failure>,
+}
+
+impl CaseReport {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.failures.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct SuiteReport {
+    pub name: String,
+    pub cases: Vec<CaseReport>,
+}
+
+impl SuiteReport {
+    pub fn passed(&self) -> bool {
+        self.cases.iter().all(CaseReport::passed)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.cases.iter().filter(|c| !c.passed()).count()
+    }
+
+    /// The report in JUnit XML format
+    pub fn junit_xml(&self) -> String {
+        let total: Duration = self.cases.iter().map(|c| c.duration).sum();
+        let errors = self.cases.iter().filter(|c| c.error.is_some()).count();
+        let failures = self
+            .cases
+            .iter()
+            .filter(|c| c.error.is_none() && !c.failures.is_empty())
+            .count();
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">",
+            escape_xml(&self.name),
+            self.cases.len(),
+            failures,
+            errors,
+            total.as_secs_f64()
+        );
+        for case in &self.cases {
+            let _ = write!(
+                xml,
+                "  <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                escape_xml(&self.name),
+                escape_xml(&case.name),
+                case.duration.as_secs_f64()
+            );
+            if case.passed() {
+                xml.push_str("/>\n");
+                continue;
+            }
+            xml.push_str(">\n");
+            if let Some(error) = &case.error {
+                let _ = writeln!(xml, "    <error message=\"{}\"/>", escape_xml(error));
+            }
+            for failure in &case.failures {
+                let _ = writeln!(
+                    xml,
+                    "    <failure message=\"{}\">{}</failure>",
+                    escape_xml(&failure.message),
+                    escape_xml(&format!(
+                        "expected: {}\nactual: {}",
+                        failure.expected, failure.actual
+                    ))
+                );
+            }
+            xml.push_str("  </testcase>\n");
+        }
+        xml.push_str("</testsuite>\n");
+        xml
+    }
+
+    /// A diff style summary of every failed case
+    pub fn failure_diff(&self) -> String {
+        let mut out = String::new();
+        for case in self.cases.iter().filter(|c| !c.passed()) {
+            let _ = writeln!(out, "FAIL {} ({:?})", case.name, case.utterance);
+            if let Some(error) = &case.error {
+                let _ = writeln!(out, "  error: {}", error);
+            }
+            for failure in &case.failures {
+                let _ = writeln!(out, "  {}", failure.message);
+                let _ = writeln!(out, "  - {}", failure.expected);
+                let _ = writeln!(out, "  + {}", failure.actual);
+            }
+        }
+        out
+    }
+}
+
+/// Recorded responses for running a suite without the network, keyed on everything a case
+/// sends: its user ID, utterance and RequestInfo fields
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Cassette {
+    pub responses: BTreeMap<String, Value>,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Result<Cassette> {
+        let file = std::fs::File::open(path).map_err(|e| HoundifyError::new(e.into()))?;
+        serde_json::from_reader(file).map_err(|e| HoundifyError::new(e.into()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| HoundifyError::new(e.into()))?;
+        std::fs::write(path, json).map_err(|e| HoundifyError::new(e.into()))
+    }
+
+    /// The key `case` is recorded under: a JSON array of its user ID, utterance and
+    /// RequestInfo fields
+    pub fn key(case: &TestCase) -> String {
+        serde_json::json!([case.user_id(), case.utterance, case.request_info]).to_string()
+    }
+
+    /// The recorded response to `case`
+    pub fn response(&self, case: &TestCase) -> Result<HoundServerResponse> {
+        let recorded = self.responses.get(&Self::key(case)).ok_or_else(|| {
+            let msg = format!("No recorded response for {:?}", case.utterance);
+            HoundifyError::new(msg.into())
+        })?;
+        serde_json::from_value(recorded.clone()).map_err(|e| HoundifyError::new(e.into()))
+    }
+}
+
+impl TestSuite {
+    /// Load a suite from a `.json` file, or with the `yaml` feature, a `.yaml` or `.yml` file
+    pub fn load(path: &Path) -> Result<TestSuite> {
+        let contents = std::fs::read_to_string(path).map_err(|e| HoundifyError::new(e.into()))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            #[cfg(feature = "yaml")]
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(&contents).map_err(|e| HoundifyError::new(e.into()))
+            }
+            _ => serde_json::from_str(&contents).map_err(|e| HoundifyError::new(e.into())),
+        }
+    }
+
+    /// Run every case through `client`
+    pub fn run(&self, client: &Client) -> SuiteReport {
+        self.run_with(|case| send(client, case))
+    }
+
+    /// Run every case against the responses recorded in `cassette`
+    pub fn replay(&self, cassette: &Cassette) -> SuiteReport {
+        self.run_with(|case| cassette.response(case))
+    }
+
+    /// Run every case through `client`, recording the responses into `cassette`
+    pub fn record(&self, client: &Client, cassette: &mut Cassette) -> SuiteReport {
+        self.run_with(|case| {
+            let response = send(client, case)?;
+            if let Ok(recorded) = serde_json::to_value(&response) {
+                cassette.responses.insert(Cassette::key(case), recorded);
+            }
+            Ok(response)
+        })
+    }
+
+    fn run_with<F>(&self, mut query: F) -> SuiteReport
+    where
+        F: FnMut(&TestCase) -> Result<HoundServerResponse>,
+    {
+        let cases = self
+            .tests
+            .iter()
+            .map(|case| {
+                let start = Instant::now();
+                let response = query(case);
+                let duration = start.elapsed();
+                let (error, failures) = match response {
+                    Ok(r) => (None, check(&case.expect, &r)),
+                    Err(e) => (Some(e.to_string()), vec![]),
+                };
+                CaseReport {
+                    name: case.display_name().to_string(),
+                    utterance: case.utterance.clone(),
+                    duration,
+                    error,
+                    failures,
+                }
+            })
+            .collect();
+        SuiteReport {
+            name: self.name.clone(),
+            cases,
+        }
+    }
+}
+
+fn send(client: &Client, case: &TestCase) -> Result<HoundServerResponse> {
+    let mut request_info = RequestInfo::new();
+    for (k, v) in &case.request_info {
+        request_info.set(k.clone(), v.clone());
+    }
+    client.text_query(TextQuery::new(
+        &case.utterance,
+        case.user_id(),
+        request_info,
+    ))
+}
+
+/// Check `response` against `expect`, returning every failed expectation
+pub fn check(expect: &Expectation, response: &HoundServerResponse) -> Vec<Failure> {
+    let mut failures = vec![];
+    if !response.is_ok() {
+        failures.push(Failure {
+            message: "Status".to_string(),
+            expected: "OK".to_string(),
+            actual: format!(
+                "{} {}",
+                response.status,
+                response.error_message.as_deref().unwrap_or("")
+            ),
+        });
+        return failures;
+    }
+    let results = Value::Array(
+        response
+            .all_results
+            .iter()
+            .flatten()
+            .cloned()
+            .map(Value::Object)
+            .collect(),
+    );
+    let first = results.get(0);
+    let field = |name: &str| {
+        first
+            .and_then(|r| r.get(name))
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_string()
+    };
+
+    if let Some(kind) = &expect.command_kind {
+        let actual = field("CommandKind");
+        if &actual != kind {
+            failures.push(Failure {
+                message: "CommandKind".to_string(),
+                expected: kind.clone(),
+                actual,
+            });
+        }
+    }
+    for (name, substrings) in [
+        ("WrittenResponse", &expect.written_response_contains),
+        ("SpokenResponse", &expect.spoken_response_contains),
+    ]
+    .iter()
+    {
+        let actual = field(name);
+        for s in substrings.iter() {
+            if !actual.contains(s.as_str()) {
+                failures.push(Failure {
+                    message: format!("{} contains", name),
+                    expected: format!("...{}...", s),
+                    actual: actual.clone(),
+                });
+            }
+        }
+    }
+    for assertion in &expect.json_path {
+        let selected = match json_path(&results, &assertion.path) {
+            Ok(v) => v,
+            Err(e) => {
+                failures.push(Failure {
+                    message: format!("JSONPath {}", assertion.path),
+                    expected: "a valid JSONPath expression".to_string(),
+                    actual: e,
+                });
+                continue;
+            }
+        };
+        let actual = Value::Array(selected.iter().map(|v| (*v).clone()).collect()).to_string();
+        let mut fail = |expected: String| {
+            failures.push(Failure {
+                message: format!("JSONPath {}", assertion.path),
+                expected,
+                actual: actual.clone(),
+            })
+        };
+        let no_condition = assertion.equals.is_none()
+            && assertion.contains.is_none()
+            && assertion.exists.is_none();
+        if (assertion.exists == Some(true) || no_condition) && selected.is_empty() {
+            fail("at least one value".to_string());
+        }
+        if assertion.exists == Some(false) && !selected.is_empty() {
+            fail("no value".to_string());
+        }
+        if let Some(expected) = &assertion.equals {
+            if !selected.contains(&expected) {
+                fail(expected.to_string());
+            }
+        }
+        if let Some(expected) = &assertion.contains {
+            if !selected
+                .iter()
+                .any(|v| matches!(v.as_str(), Some(s) if s.contains(expected.as_str())))
+            {
+                fail(format!("...{}...", expected));
+            }
+        }
+    }
+    failures
+}
+
+/// Evaluate a JSONPath expression supporting `$`, `.Field`, `['Field']`, `[n]` and `[*]`
+pub fn json_path<'a>(root: &'a Value, path: &str) -> std::result::Result<Vec<&'a Value>, String> {
+    let rest = path
+        .strip_prefix('$')
+        .ok_or_else(|| format!("{:?} must start with '$'", path))?;
+    let chars: Vec<char> = rest.chars().collect();
+    let mut selected = vec![root];
+    let mut i = 0;
+    while i < chars.len() {
+        let step = match chars[i] {
+            '.' => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && chars[i] != '.' && chars[i] != '[' {
+                    i += 1;
+                }
+                let name: String = chars[start..i].iter().collect();
+                if name.is_empty() {
+                    return Err(format!("Empty field name at position {}", start + 1));
+                }
+                Step::Field(name)
+            }
+            '[' => {
+                let start = i + 1;
+                let end = (start..chars.len())
+                    .find(|&j| chars[j] == ']')
+                    .ok_or_else(|| format!("Unclosed '[' at position {}", i + 1))?;
+                i = end + 1;
+                let inner: String = chars[start..end].iter().collect();
+                let inner = inner.trim();
+                if inner == "*" {
+                    Step::Wildcard
+                } else if let Ok(n) = inner.parse::<usize>() {
+                    Step::Index(n)
+                } else if inner.len() >= 2
+                    && ((inner.starts_with('\'') && inner.ends_with('\''))
+                        || (inner.starts_with('"') && inner.ends_with('"')))
+                {
+                    Step::Field(inner[1..inner.len() - 1].to_string())
+                } else {
+                    return Err(format!("Invalid subscript [{}]", inner));
+                }
+            }
+            c => return Err(format!("Unexpected {:?} at position {}", c, i + 1)),
+        };
+        selected = selected
+            .into_iter()
+            .flat_map(|v| -> Vec<&Value> {
+                match (&step, v) {
+                    (Step::Field(name), Value::Object(m)) => m.get(name).into_iter().collect(),
+                    (Step::Index(n), Value::Array(a)) => a.get(*n).into_iter().collect(),
+                    (Step::Wildcard, Value::Array(a)) => a.iter().collect(),
+                    (Step::Wildcard, Value::Object(m)) => m.values().collect(),
+                    _ => vec![],
+                }
+            })
+            .collect();
+    }
+    Ok(selected)
+}
+
+enum Step {
+    Field(String),
+    Index(usize),
+    Wildcard,
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod harness_tests {
+    use super::*;
+    use crate::test_server;
+    use serde_json::json;
+
+    fn suite() -> TestSuite {
+        serde_json::from_value(json!({
+            "name": "arithmetic",
+            "tests": [
+                {
+                    "name": "one plus one",
+                    "utterance": "what is one plus one",
+                    "expect": {
+                        "command_kind": "InformationCommand",
+                        "written_response_contains": ["2"],
+                        "json_path": [{"path": "$[0].SpokenResponse", "contains": "2"}]
+                    }
+                },
+                {
+                    "utterance": "what is two plus two",
+                    "expect": {
+                        "written_response_contains": ["4"],
+                        "json_path": [{"path": "$[*]['CommandKind']", "equals": "MusicCommand"}]
+                    }
+                }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_load_yaml() {
+        let suite = TestSuite::load(Path::new("tests/arithmetic.yaml")).unwrap();
+        assert_eq!(suite.name, "arithmetic");
+        assert_eq!(suite.tests[0], self::suite().tests[0]);
+    }
+
+    #[test]
+    fn test_json_path() {
+        let v = json!([{"A": {"B": [1, 2]}}, {"A": {"B": [3]}}]);
+        assert_eq!(json_path(&v, "$[0].A.B[1]").unwrap(), vec![&json!(2)]);
+        assert_eq!(
+            json_path(&v, "$[*].A['B'][0]").unwrap(),
+            vec![&json!(1), &json!(3)]
+        );
+        assert!(json_path(&v, "$[2].A").unwrap().is_empty());
+        assert!(json_path(&v, "A.B").is_err());
+        assert!(json_path(&v, "$[0").is_err());
+    }
+
+    #[test]
+    fn test_run_against_client() {
+        let url = test_server::serve(|request| {
+            let answer = if request.path.contains("one+plus+one") {
+                "2"
+            } else {
+                "5"
+            };
+            test_server::ok_response(answer, "Arithmetic", 0.5)
+        });
+        let client = Client::new(&url, "client", "a2V5", None);
+        let report = suite().run(&client);
+
+        assert!(report.cases[0].passed(), "{}", report.failure_diff());
+        assert!(!report.cases[1].passed());
+        assert_eq!(report.failed(), 1);
+        assert_eq!(report.cases[1].failures.len(), 2);
+        assert_eq!(
+            report.failure_diff(),
+            concat!(
+                "FAIL what is two plus two (\"what is two plus two\")\n",
+                "  WrittenResponse contains\n",
+                "  - ...4...\n",
+                "  + 5\n",
+                "  JSONPath $[*]['CommandKind']\n",
+                "  - \"MusicCommand\"\n",
+                "  + [\"InformationCommand\"]\n",
+            )
+        );
+        let xml = report.junit_xml();
+        assert!(
+            xml.contains("<testsuite name=\"arithmetic\" tests=\"2\" failures=\"1\" errors=\"0\"")
+        );
+        assert!(xml.contains("<failure message=\"WrittenResponse contains\">"));
+    }
+
+    #[test]
+    fn test_record_and_replay_cassette() {
+        let url = test_server::serve(|_| test_server::ok_response("2", "Arithmetic", 0.5));
+        let client = Client::new(&url, "client", "a2V5", None);
+        let mut cassette = Cassette::default();
+        suite().record(&client, &mut cassette);
+        assert_eq!(cassette.responses.len(), 2);
+        let report = suite().replay(&cassette);
+        assert!(report.cases[0].passed(), "{}", report.failure_diff());
+    }
+
+    #[test]
+    fn test_replay_cassette() {
+        let mut cassette = Cassette::default();
+        let response: Value =
+            serde_json::from_str(&test_server::ok_response("2", "Arithmetic", 0.5)).unwrap();
+        let suite = suite();
+        cassette
+            .responses
+            .insert(Cassette::key(&suite.tests[0]), response);
+        let report = suite.replay(&cassette);
+        assert!(report.cases[0].passed());
+        assert!(report.cases[1].error.is_some());
+        assert!(report.junit_xml().contains("errors=\"1\""));
+    }
+
+    #[test]
+    fn test_cassette_key() {
+        let case = suite().tests.remove(0);
+        let mut other_user = case.clone();
+        other_user.user_id = Some("someone-else".to_string());
+        let mut french = case.clone();
+        french
+            .request_info
+            .insert("InputLanguageIETFTag".to_string(), json!("fr"));
+        assert_eq!(
+            Cassette::key(&case),
+            r#"["houndify-harness","what is one plus one",{}]"#
+        );
+        assert_ne!(Cassette::key(&case), Cassette::key(&other_user));
+        assert_ne!(Cassette::key(&case), Cassette::key(&french));
+    }
+}
@@ -1,9 +1,67 @@
-pub use crate::client::{Client, Result, DEFAULT_API_ENDPOINT};
-pub use crate::error::{HoundifyError, InvalidRequestInfoError};
-pub use crate::query::{RequestInfo, TextQuery, VoiceQuery};
-pub use crate::response::{HoundServerResponse, Disambiguation, DisambiguationChoice, DomainUsage, BuildInfo};
+pub use crate::audio::{AudioFormat, TRANSCODE_SAMPLE_RATE};
+pub use crate::audio_source::{
+    audio_channel, AudioFrame, AudioSource, AudioSpec, ChannelSource, IterSource, ReadSource,
+    Sample,
+};
+pub use crate::batch::{BatchOutcome, BatchProgress, BatchReport, BatchRunner};
+pub use crate::cache::{ResponseCache, DEFAULT_KEY_FIELDS, DEFAULT_UNCACHED_KINDS};
+pub use crate::client::{
+    Client, RequestInfoPlacement, Result, DEFAULT_API_ENDPOINT, DEFAULT_MAX_REQUEST_INFO_HEADER_LEN,
+};
+pub use crate::client_action::{ClientActionHandler, ClientActionOutcome, ClientActions};
+pub use crate::compression::CompressionStats;
+pub use crate::config::{
+    ClientConfig, ConfigFile, ENV_API_URL, ENV_CLIENT_ID, ENV_CLIENT_KEY, ENV_PROFILE,
+};
+#[cfg(feature = "domains")]
+pub use crate::domains::DomainResult;
+pub use crate::error::{
+    BudgetExceededError, ConfigError, HoundifyError, InvalidExpressionError,
+    InvalidRequestInfoError, RateLimitedError, UnsupportedAudioError, UnsupportedFormatError,
+};
+pub use crate::expression::Expression;
+pub use crate::format::{
+    is_supported_format_version, FormatVersionPolicy, FormatWarningCallback, COMMAND_RESULT_SCHEMA,
+    HOUND_SERVER_RESPONSE_SCHEMA, RESPONSE_FORMAT, SUPPORTED_FORMAT_MAJOR_VERSION,
+    TEMPLATE_DATA_SCHEMA,
+};
+pub use crate::location::{
+    GpsdLocationProvider, Location, LocationProvider, StaticLocationProvider, DEFAULT_GPSD_ADDRESS,
+};
+pub use crate::metadata::{ClientMetadata, SDK};
+pub use crate::query::{Query, QueryBody, RequestInfo, TextQuery, VoiceQuery};
+pub use crate::rate_limit::{RateLimiter, WaitCallback};
+pub use crate::response::{
+    BuildInfo, Disambiguation, DisambiguationChoice, DomainUsage, HoundServerResponse,
+    ImageTemplate, LazyHoundServerResponse, LocalOrRemote, ProcessingStats, SimpleTextTemplate,
+    Status, TemplateData, TemplateListItem, TwoColumnRow, TwoColumnTemplate, VerticalTemplateList,
+};
+pub use crate::usage::{UsageSnapshot, UsageTotals, UsageTracker};
+pub use reqwest::header::HeaderMap;
+pub use reqwest::Method;
 
+mod audio;
+mod audio_source;
+mod batch;
+mod cache;
 mod client;
+mod client_action;
+mod compression;
+mod config;
+#[cfg(feature = "domains")]
+pub mod domains;
 mod error;
+mod expression;
+mod format;
+pub mod harness;
+mod location;
+mod metadata;
 mod query;
+mod rate_limit;
+pub mod render;
 mod response;
+#[cfg(test)]
+mod test_server;
+#[cfg(test)]
+mod test_strategies;
+mod usage;
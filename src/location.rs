@@ -0,0 +1,368 @@
+use crate::query::RequestInfo;
+use serde::Deserialize;
+use std::fmt::Debug;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Default address of a local gpsd daemon
+pub static DEFAULT_GPSD_ADDRESS: &str = "127.0.0.1:2947";
+
+/// The location fields of a RequestInfo
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Unix timestamp, in seconds, of when the position was determined
+    pub position_time: Option<u64>,
+    /// Accuracy of the position, in meters
+    pub horizontal_accuracy: Option<f64>,
+    pub street: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub country: Option<String>,
+}
+
+impl Location {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Location {
+            latitude,
+            longitude,
+            ..Default::default()
+        }
+    }
+
+    /// Fill in the location fields of `request_info` which are not already set
+    pub fn apply(&self, request_info: &mut RequestInfo) {
+        if !(-90.0..=90.0).contains(&self.latitude) || !(-180.0..=180.0).contains(&self.longitude) {
+            return;
+        }
+        if !request_info.contains("Latitude") && !request_info.contains("Longitude") {
+            request_info.latitude(self.latitude);
+            request_info.longitude(self.longitude);
+        }
+        if let Some(v) = self.position_time {
+            if !request_info.contains("PositionTime") {
+                request_info.position_time(v);
+            }
+        }
+        if let Some(v) = self.horizontal_accuracy {
+            if !request_info.contains("PositionHorizontalAccuracy") {
+                request_info.position_horizontal_accuracy(v);
+            }
+        }
+        let fields = [
+            ("Street", &self.street),
+            ("City", &self.city),
+            ("State", &self.state),
+            ("Country", &self.country),
+        ];
+        for (k, v) in fields.iter() {
+            if let Some(v) = v {
+                if !request_info.contains(k) {
+                    request_info.set(k.to_string(), v.as_str().into());
+                }
+            }
+        }
+    }
+}
+
+/// A source of the device's current location, consulted by the client before each query
+pub trait LocationProvider: Debug + Send + Sync {
+    /// The current location, or `None` if it is not known
+    fn location(&self) -> Option<Location>;
+}
+
+/// Always reports the same location
+#[derive(Debug, Clone)]
+pub struct StaticLocationProvider {
+    location: Location,
+}
+
+impl StaticLocationProvider {
+    pub fn new(location: Location) -> Self {
+        StaticLocationProvider { location }
+    }
+}
+
+impl LocationProvider for StaticLocationProvider {
+    fn location(&self) -> Option<Location> {
+        Some(self.location.clone())
+    }
+}
+
+/// Reads the current position from a gpsd daemon.
+///
+/// A lookup connects to the daemon, enables JSON watch mode and waits for the first "TPV"
+/// report with a 2D or 3D fix. Its result, fix or not, is reused for `cache_ttl`, so that
+/// consecutive queries do not each wait on the daemon.
+///
+/// Reference: https://gpsd.gitlab.io/gpsd/gpsd_json.html
+#[derive(Debug, Clone)]
+pub struct GpsdLocationProvider {
+    address: SocketAddr,
+    timeout: Duration,
+    cache_ttl: Duration,
+    /// The last lookup, shared by clones
+    last: Arc<Mutex<Option<Lookup>>>,
+}
+
+impl GpsdLocationProvider {
+    pub fn new(address: SocketAddr) -> Self {
+        GpsdLocationProvider {
+            address,
+            timeout: Duration::from_secs(1),
+            cache_ttl: Duration::from_secs(5),
+            last: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// How long to wait for a fix before giving up, including connecting. Defaults to 1 second.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How long the result of a lookup is reused. Defaults to 5 seconds; zero looks the
+    /// position up for every query.
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    fn read_location(&self) -> io::Result<Option<Location>> {
+        let deadline = Instant::now() + self.timeout;
+        let mut stream = TcpStream::connect_timeout(&self.address, self.timeout)?;
+        stream.write_all(b"?WATCH={\"enable\":true,\"json\":true};\n")?;
+        let stream = DeadlineStream { stream, deadline };
+        Ok(read_fix(BufReader::new(stream), deadline))
+    }
+}
+
+impl Default for GpsdLocationProvider {
+    fn default() -> Self {
+        GpsdLocationProvider::new(DEFAULT_GPSD_ADDRESS.parse().unwrap())
+    }
+}
+
+/// When a gpsd lookup was made, and the location it found
+type Lookup = (Instant, Option<Location>);
+
+impl LocationProvider for GpsdLocationProvider {
+    fn location(&self) -> Option<Location> {
+        if let Some((at, location)) = &*self.last.lock().unwrap() {
+            if at.elapsed() < self.cache_ttl {
+                return location.clone();
+            }
+        }
+        // The lock is not held during the lookup, so a slow daemon does not block clones
+        // that only need to read the cache.
+        let location = self.read_location().ok().flatten();
+        *self.last.lock().unwrap() = Some((Instant::now(), location.clone()));
+        location
+    }
+}
+
+/// A stream whose reads wait no later than `deadline`, so that a daemon sending reports
+/// without a fix cannot hold a lookup open
+struct DeadlineStream {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::from_secs(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no gpsd fix in time",
+            ));
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+#[derive(Deserialize)]
+struct Tpv {
+    class: String,
+    mode: Option<u8>,
+    time: Option<String>,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    eph: Option<f64>,
+    epx: Option<f64>,
+    epy: Option<f64>,
+}
+
+/// Read gpsd reports until one carries a usable fix, giving up at `deadline`
+fn read_fix<R: BufRead>(reader: R, deadline: Instant) -> Option<Location> {
+    for line in reader.lines() {
+        if Instant::now() >= deadline {
+            return None;
+        }
+        let line = line.ok()?;
+        let tpv: Tpv = match serde_json::from_str(&line) {
+            Ok(tpv) => tpv,
+            Err(_) => continue,
+        };
+        if tpv.class != "TPV" || tpv.mode.unwrap_or(0) < 2 {
+            continue;
+        }
+        let (latitude, longitude) = match (tpv.lat, tpv.lon) {
+            (Some(lat), Some(lon)) => (lat, lon),
+            _ => continue,
+        };
+        let horizontal_accuracy = tpv.eph.or(match (tpv.epx, tpv.epy) {
+            (Some(x), Some(y)) => Some(x.max(y)),
+            _ => None,
+        });
+        let position_time = tpv.time.as_deref().and_then(parse_timestamp).or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs())
+        });
+        return Some(Location {
+            latitude,
+            longitude,
+            position_time,
+            horizontal_accuracy,
+            ..Default::default()
+        });
+    }
+    None
+}
+
+/// Parse an ISO 8601 UTC timestamp such as "2020-02-04T01:57:14.000Z" into Unix seconds
+fn parse_timestamp(s: &str) -> Option<u64> {
+    let s = s.strip_suffix('Z')?;
+    let (date, time) = s.split_at(s.find('T')?);
+    let mut date = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time[1..].split(':');
+    let hh: i64 = time.next()?.parse().ok()?;
+    let mm: i64 = time.next()?.parse().ok()?;
+    let ss: f64 = time.next()?.parse().ok()?;
+
+    // Days since the epoch of a proleptic Gregorian date
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let secs = days * 86_400 + hh * 3_600 + mm * 60 + ss as i64;
+    if secs < 0 {
+        None
+    } else {
+        Some(secs as u64)
+    }
+}
+
+#[cfg(test)]
+mod location_tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_timestamp("2020-02-04T01:57:14.000Z"),
+            Some(1_580_781_434)
+        );
+        assert_eq!(parse_timestamp("2020-02-04 01:57:14"), None);
+    }
+
+    #[test]
+    fn test_gpsd_provider_reads_fix() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut watch = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut watch)
+                .unwrap();
+            assert!(watch.starts_with("?WATCH="));
+            let reports = concat!(
+                "{\"class\":\"VERSION\",\"release\":\"3.20\"}\n",
+                "{\"class\":\"TPV\",\"mode\":1}\n",
+                "{\"class\":\"TPV\",\"mode\":3,\"time\":\"2020-02-04T01:57:14.000Z\",",
+                "\"lat\":43.7,\"lon\":-79.4,\"epx\":8.5,\"epy\":12.0}\n",
+            );
+            stream.write_all(reports.as_bytes()).unwrap();
+        });
+
+        let location = GpsdLocationProvider::new(address).location().unwrap();
+        server.join().unwrap();
+        assert_eq!(location.latitude, 43.7);
+        assert_eq!(location.longitude, -79.4);
+        assert_eq!(location.position_time, Some(1_580_781_434));
+        assert_eq!(location.horizontal_accuracy, Some(12.0));
+    }
+
+    #[test]
+    fn test_read_fix_gives_up_at_deadline() {
+        // Reports keep coming, but none has a fix
+        let reports = "{\"class\":\"TPV\",\"mode\":1}\n".as_bytes();
+        let endless = BufReader::new(Endless(reports));
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(50);
+        assert_eq!(read_fix(endless, deadline), None);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    struct Endless(&'static [u8]);
+
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_gpsd_provider_caches_lookups() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let report = "{\"class\":\"TPV\",\"mode\":2,\"lat\":43.7,\"lon\":-79.4}\n";
+            stream.write_all(report.as_bytes()).unwrap();
+            // A second lookup would need a second connection
+            listener.set_nonblocking(true).unwrap();
+            thread::sleep(Duration::from_millis(200));
+            listener.accept().is_ok()
+        });
+
+        let provider = GpsdLocationProvider::new(address);
+        assert!(provider.location().is_some());
+        assert_eq!(provider.clone().location(), provider.location());
+        assert!(!server.join().unwrap());
+    }
+
+    #[test]
+    fn test_apply_keeps_query_values() {
+        let mut location = Location::new(43.7, -79.4);
+        location.city = Some("Toronto".to_string());
+        location.country = Some("Canada".to_string());
+        let mut request_info = RequestInfo::new();
+        request_info.set("City".to_string(), "Ottawa".into());
+        StaticLocationProvider::new(location)
+            .location()
+            .unwrap()
+            .apply(&mut request_info);
+        assert_eq!(
+            request_info.serialize().unwrap(),
+            r#"{"City":"Ottawa","Country":"Canada","Latitude":43.7,"Longitude":-79.4}"#
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use crate::query::RequestInfo;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Value of the "SDK" RequestInfo field, naming this crate and its version
+pub static SDK: &str = concat!("houndify-sdk-rust/", env!("CARGO_PKG_VERSION"));
+
+/// Information about the client application that a client can send with every query, for
+/// Houndify's analytics and for troubleshooting. Clients send none unless it is set with
+/// `Client::set_client_metadata` or `Client::collect_client_metadata`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientMetadata {
+    /// Sent as "DeviceID"
+    pub device_id: Option<String>,
+    /// Version of the application, sent as "ClientVersion"
+    pub client_version: Option<String>,
+    /// Sent as "AppName"
+    pub app_name: Option<String>,
+    /// Sent as "OperatingSystem"
+    pub os: Option<String>,
+}
+
+impl ClientMetadata {
+    /// Metadata with no fields set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Metadata collected from the running system. The app name is the executable's name, the
+    /// operating system is reported with its architecture, and where the OS provides a machine
+    /// ID the device ID is a hash of it. The client version is left unset.
+    pub fn collect() -> Self {
+        let app_name = std::env::current_exe()
+            .ok()
+            .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()));
+        ClientMetadata {
+            device_id: machine_id().map(|id| device_id(&id)),
+            client_version: None,
+            app_name,
+            os: Some(format!(
+                "{} {}",
+                std::env::consts::OS,
+                std::env::consts::ARCH
+            )),
+        }
+    }
+
+    pub fn device_id(mut self, v: &str) -> Self {
+        self.device_id = Some(v.to_string());
+        self
+    }
+
+    pub fn client_version(mut self, v: &str) -> Self {
+        self.client_version = Some(v.to_string());
+        self
+    }
+
+    pub fn app_name(mut self, v: &str) -> Self {
+        self.app_name = Some(v.to_string());
+        self
+    }
+
+    pub fn os(mut self, v: &str) -> Self {
+        self.os = Some(v.to_string());
+        self
+    }
+
+    /// Fill in the metadata fields of `request_info` which are not already set
+    pub fn apply(&self, request_info: &mut RequestInfo) {
+        let fields = [
+            ("DeviceID", &self.device_id),
+            ("ClientVersion", &self.client_version),
+            ("AppName", &self.app_name),
+            ("OperatingSystem", &self.os),
+        ];
+        for (k, v) in fields.iter() {
+            if let Some(v) = v {
+                if !request_info.contains(k) {
+                    request_info.set(k.to_string(), Value::String(v.clone()));
+                }
+            }
+        }
+    }
+}
+
+fn machine_id() -> Option<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .map(|id| id.trim().to_string())
+        .find(|id| !id.is_empty())
+}
+
+/// The machine ID is hashed so that the real one is never sent
+fn device_id(machine_id: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(b"houndify-sdk-rust:");
+    hasher.input(machine_id.as_bytes());
+    hasher
+        .result()
+        .iter()
+        .take(16)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod metadata_tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sdk_version() {
+        assert_eq!(
+            SDK,
+            format!("houndify-sdk-rust/{}", env!("CARGO_PKG_VERSION"))
+        );
+    }
+
+    #[test]
+    fn test_collect_and_apply() {
+        let metadata = ClientMetadata::collect()
+            .client_version("2.1.0")
+            .device_id("kitchen");
+        assert!(metadata
+            .os
+            .as_ref()
+            .unwrap()
+            .starts_with(std::env::consts::OS));
+        assert!(metadata.app_name.is_some());
+
+        let mut request_info = RequestInfo::new();
+        request_info.set("DeviceID".to_string(), json!("hallway"));
+        metadata.apply(&mut request_info);
+        assert_eq!(request_info.get("DeviceID"), Some(&json!("hallway")));
+        assert_eq!(request_info.get("ClientVersion"), Some(&json!("2.1.0")));
+        assert!(request_info.contains("OperatingSystem"));
+    }
+
+    #[test]
+    fn test_device_id_is_hashed() {
+        let id = device_id("0123456789abcdef");
+        assert_eq!(id.len(), 32);
+        assert!(!id.contains("0123456789abcdef"));
+        assert_eq!(id, device_id("0123456789abcdef"));
+    }
+}
@@ -1,10 +1,63 @@
 use crate::error::{HoundifyError, InvalidRequestInfoError};
+use crate::response::HoundServerResponse;
 use crate::Result;
+use reqwest::header::HeaderMap;
+use reqwest::Method;
+use serde::de::DeserializeOwned;
 use serde_json::{Map, Number, Value};
+use std::io::Read;
 use url::form_urlencoded;
 
+/// A request to a Houndify endpoint. `Client::execute` signs it, merges the client's base
+/// RequestInfo into its RequestInfo, sends it and decodes the response. Implement it to call
+/// endpoints the SDK does not cover.
 pub trait Query {
+    /// The type the JSON response is decoded into
+    type Response: DeserializeOwned;
+
+    /// The full URL of the request, given the client's API URL
     fn get_url(&self, api_url: &str) -> String;
+
+    fn user_id(&self) -> &str;
+
+    fn request_info(&self) -> &RequestInfo;
+
+    fn method(&self) -> Method {
+        Method::GET
+    }
+
+    /// Headers sent in addition to the authentication and RequestInfo headers
+    fn headers(&self) -> HeaderMap {
+        HeaderMap::new()
+    }
+
+    /// The request body. Called once, just before the request is sent.
+    fn body(&mut self) -> Option<QueryBody> {
+        None
+    }
+
+    /// Whether the endpoint accepts the RequestInfo at the start of the body, with its length
+    /// in the "Houndify-Request-Info-Length" header, instead of in the "Houndify-Request-Info"
+    /// header. A GET query is then sent with POST.
+    fn accepts_request_info_body(&self) -> bool {
+        false
+    }
+}
+
+/// The body of a `Query`
+pub enum QueryBody {
+    Bytes(Vec<u8>),
+    /// Streamed with chunked transfer encoding
+    Reader(Box<dyn Read + Send>),
+}
+
+impl std::fmt::Debug for QueryBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            QueryBody::Bytes(b) => write!(f, "Bytes({} bytes)", b.len()),
+            QueryBody::Reader(_) => f.write_str("Reader"),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -12,6 +65,18 @@ pub struct RequestInfo {
     request_info_map: Map<String, Value>,
 }
 
+impl Default for RequestInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Map<String, Value>> for RequestInfo {
+    fn from(request_info_map: Map<String, Value>) -> Self {
+        RequestInfo { request_info_map }
+    }
+}
+
 impl RequestInfo {
     pub fn new() -> Self {
         RequestInfo {
@@ -20,8 +85,8 @@ impl RequestInfo {
     }
 
     /// Set the latitude of the request
-    pub fn latitude(&mut self, v: f64) -> Option<InvalidRequestInfoError> {
-        if v < -90.0 || v > 90.0 {
+    pub fn latitude(&mut self, v: f64) -> Option<InvalidRequestInfoError<'_>> {
+        if !(-90.0..=90.0).contains(&v) {
             return Some(InvalidRequestInfoError::new(
                 "Latitude must between -90 and 90",
             ));
@@ -30,15 +95,14 @@ impl RequestInfo {
             Some(n) => n,
             None => return Some(InvalidRequestInfoError::new("Cannot parse latitude")),
         };
-        &self
-            .request_info_map
+        self.request_info_map
             .insert("Latitude".to_string(), Value::Number(n));
         None
     }
 
     /// Set the longitude of the request
-    pub fn longitude(&mut self, v: f64) -> Option<InvalidRequestInfoError> {
-        if v < -180.0 || v > 180.0 {
+    pub fn longitude(&mut self, v: f64) -> Option<InvalidRequestInfoError<'_>> {
+        if !(-180.0..=180.0).contains(&v) {
             return Some(InvalidRequestInfoError::new(
                 "Longitude must between -180 and 180",
             ));
@@ -47,39 +111,50 @@ impl RequestInfo {
             Some(n) => n,
             None => return Some(InvalidRequestInfoError::new("Cannot parse longitude")),
         };
-        &self
-            .request_info_map
+        self.request_info_map
             .insert("Longitude".to_string(), Value::Number(n));
         None
     }
 
     /// Set timestamp
-    pub fn timestamp(&mut self, v: u64) -> Option<InvalidRequestInfoError> {
-        &self
-            .request_info_map
+    pub fn timestamp(&mut self, v: u64) -> Option<InvalidRequestInfoError<'_>> {
+        self.request_info_map
             .insert("TimeStamp".to_string(), Value::Number(Number::from(v)));
         None
     }
 
     /// Set ClientID
-    pub fn client_id(&mut self, v: &str) -> Option<InvalidRequestInfoError> {
-        &self
-            .request_info_map
+    pub fn client_id(&mut self, v: &str) -> Option<InvalidRequestInfoError<'_>> {
+        self.request_info_map
             .insert("ClientID".to_string(), Value::String(v.to_string()));
         None
     }
 
     /// Set PositionTime
-    pub fn position_time(&mut self, v: u64) -> Option<InvalidRequestInfoError> {
-        &self
-            .request_info_map
+    pub fn position_time(&mut self, v: u64) -> Option<InvalidRequestInfoError<'_>> {
+        self.request_info_map
             .insert("PositionTime".to_string(), Value::Number(Number::from(v)));
         None
     }
 
+    /// Set PositionHorizontalAccuracy, in meters
+    pub fn position_horizontal_accuracy(&mut self, v: f64) -> Option<InvalidRequestInfoError<'_>> {
+        let n = match Number::from_f64(v) {
+            Some(n) => n,
+            None => {
+                return Some(InvalidRequestInfoError::new(
+                    "Cannot parse position horizontal accuracy",
+                ))
+            }
+        };
+        self.request_info_map
+            .insert("PositionHorizontalAccuracy".to_string(), Value::Number(n));
+        None
+    }
+
     /// Set InputLanguageEnglishName
-    pub fn input_language_english_name(&mut self, v: &str) -> Option<InvalidRequestInfoError> {
-        &self.request_info_map.insert(
+    pub fn input_language_english_name(&mut self, v: &str) -> Option<InvalidRequestInfoError<'_>> {
+        self.request_info_map.insert(
             "InputLanguageEnglishName".to_string(),
             Value::String(v.to_string()),
         );
@@ -87,8 +162,8 @@ impl RequestInfo {
     }
 
     /// Set InputLanguageNativeName
-    pub fn input_language_native_name(&mut self, v: &str) -> Option<InvalidRequestInfoError> {
-        &self.request_info_map.insert(
+    pub fn input_language_native_name(&mut self, v: &str) -> Option<InvalidRequestInfoError<'_>> {
+        self.request_info_map.insert(
             "InputLanguageNativeName".to_string(),
             Value::String(v.to_string()),
         );
@@ -96,8 +171,8 @@ impl RequestInfo {
     }
 
     /// Set InputLanguageIETFTag
-    pub fn input_language_ietf_tag(&mut self, v: &str) -> Option<InvalidRequestInfoError> {
-        &self.request_info_map.insert(
+    pub fn input_language_ietf_tag(&mut self, v: &str) -> Option<InvalidRequestInfoError<'_>> {
+        self.request_info_map.insert(
             "InputLanguageIETFTag".to_string(),
             Value::String(v.to_string()),
         );
@@ -105,8 +180,8 @@ impl RequestInfo {
     }
 
     /// Set OutputLanguageEnglishName
-    pub fn output_language_english_name(&mut self, v: &str) -> Option<InvalidRequestInfoError> {
-        &self.request_info_map.insert(
+    pub fn output_language_english_name(&mut self, v: &str) -> Option<InvalidRequestInfoError<'_>> {
+        self.request_info_map.insert(
             "OutputLanguageEnglishName".to_string(),
             Value::String(v.to_string()),
         );
@@ -114,8 +189,8 @@ impl RequestInfo {
     }
 
     /// Set OutputLanguageNativeName
-    pub fn output_language_native_name(&mut self, v: &str) -> Option<InvalidRequestInfoError> {
-        &self.request_info_map.insert(
+    pub fn output_language_native_name(&mut self, v: &str) -> Option<InvalidRequestInfoError<'_>> {
+        self.request_info_map.insert(
             "OutputLanguageNativeName".to_string(),
             Value::String(v.to_string()),
         );
@@ -123,8 +198,8 @@ impl RequestInfo {
     }
 
     /// Set OutputLanguageIETFTag
-    pub fn output_language_ietf_tag(&mut self, v: &str) -> Option<InvalidRequestInfoError> {
-        &self.request_info_map.insert(
+    pub fn output_language_ietf_tag(&mut self, v: &str) -> Option<InvalidRequestInfoError<'_>> {
+        self.request_info_map.insert(
             "OutputLanguageIETFTag".to_string(),
             Value::String(v.to_string()),
         );
@@ -132,19 +207,41 @@ impl RequestInfo {
     }
 
     /// Set PartialTranscriptsDesired
-    pub fn partial_transcript_desired(&mut self, v: bool) -> Option<InvalidRequestInfoError> {
-        &self
-            .request_info_map
+    pub fn partial_transcript_desired(&mut self, v: bool) -> Option<InvalidRequestInfoError<'_>> {
+        self.request_info_map
             .insert("PartialTranscriptsDesired".to_string(), Value::Bool(v));
         None
     }
 
+    /// Set ConversationState
+    pub fn conversation_state(&mut self, v: Value) -> Option<InvalidRequestInfoError<'_>> {
+        self.request_info_map
+            .insert("ConversationState".to_string(), v);
+        None
+    }
+
     /// Set arbitrary RequestInfo
-    pub fn set(&mut self, k: String, v: Value) -> Option<InvalidRequestInfoError> {
-        &self.request_info_map.insert(k, v);
+    pub fn set(&mut self, k: String, v: Value) -> Option<InvalidRequestInfoError<'_>> {
+        self.request_info_map.insert(k, v);
         None
     }
 
+    /// Get the value of a RequestInfo field
+    pub fn get(&self, k: &str) -> Option<&Value> {
+        self.request_info_map.get(k)
+    }
+
+    /// Whether a RequestInfo field is set
+    pub fn contains(&self, k: &str) -> bool {
+        self.request_info_map.contains_key(k)
+    }
+
+    /// Deep merge `defaults` into this RequestInfo. Fields already set here take precedence;
+    /// where both sides hold an object, their fields are merged recursively.
+    pub fn apply_defaults(&mut self, defaults: &RequestInfo) {
+        merge_map(&mut self.request_info_map, &defaults.request_info_map);
+    }
+
     pub fn serialize(self) -> Result<String> {
         match serde_json::to_string(&self.request_info_map) {
             Ok(j) => Ok(j),
@@ -153,64 +250,178 @@ impl RequestInfo {
     }
 }
 
-#[derive(Debug)]
-pub struct TextQuery<'a> {
-    pub(crate) query: &'a str,
-    pub(crate) user_id: &'a str,
+pub(crate) fn merge_map(target: &mut Map<String, Value>, defaults: &Map<String, Value>) {
+    for (k, default) in defaults {
+        match (target.get_mut(k), default) {
+            (Some(Value::Object(target)), Value::Object(default)) => merge_map(target, default),
+            (Some(_), _) => {}
+            (None, _) => {
+                target.insert(k.clone(), default.clone());
+            }
+        }
+    }
+}
+
+/// A text query. It owns its text and user ID, so it can be stored, sent across threads and
+/// sent more than once.
+#[derive(Debug, Clone)]
+pub struct TextQuery {
+    pub(crate) query: String,
+    pub(crate) user_id: String,
     pub(crate) request_info: RequestInfo,
 }
 
-impl<'a> TextQuery<'a> {
-    pub fn new(query: &'a str, user_id: &'a str, mut request_info: RequestInfo) -> TextQuery<'a> {
-        request_info.set(
-            "SDK".to_string(),
-            Value::String("houndify-sdk-rust/1.0".to_string()),
-        ); // TODO: get the SDK version from manifest?
-        request_info.set("UserID".to_string(), Value::String(user_id.to_string()));
+impl TextQuery {
+    pub fn new<Q, U>(query: Q, user_id: U, request_info: RequestInfo) -> TextQuery
+    where
+        Q: Into<String>,
+        U: Into<String>,
+    {
         TextQuery {
-            query,
-            user_id,
+            query: query.into(),
+            user_id: user_id.into(),
             request_info,
         }
     }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    pub fn request_info(&self) -> &RequestInfo {
+        &self.request_info
+    }
+
+    pub fn request_info_mut(&mut self) -> &mut RequestInfo {
+        &mut self.request_info
+    }
 }
 
-impl Query for TextQuery<'_> {
+impl Query for TextQuery {
+    type Response = HoundServerResponse;
+
     fn get_url(&self, api_url: &str) -> String {
         let url: String = form_urlencoded::Serializer::new(format!("{}v1/text?", api_url))
             .append_pair("query", &self.query)
             .finish();
         url
     }
+
+    fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    fn request_info(&self) -> &RequestInfo {
+        &self.request_info
+    }
+
+    fn accepts_request_info_body(&self) -> bool {
+        true
+    }
 }
 
-pub struct VoiceQuery<'a> {
+pub struct VoiceQuery {
     pub(crate) audio_stream: Box<dyn std::io::Read + Send>,
-    pub(crate) user_id: &'a str,
+    pub(crate) user_id: String,
     pub(crate) request_info: RequestInfo,
 }
 
-impl Query for VoiceQuery<'_> {
+impl Query for VoiceQuery {
+    type Response = HoundServerResponse;
+
     fn get_url(&self, api_url: &str) -> String {
-        return format!("{}v1/audio", api_url);
+        format!("{}v1/audio", api_url)
+    }
+
+    fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    fn request_info(&self) -> &RequestInfo {
+        &self.request_info
+    }
+
+    fn method(&self) -> Method {
+        Method::POST
+    }
+
+    fn body(&mut self) -> Option<QueryBody> {
+        let audio = std::mem::replace(&mut self.audio_stream, Box::new(std::io::empty()));
+        Some(QueryBody::Reader(audio))
+    }
+
+    fn accepts_request_info_body(&self) -> bool {
+        true
     }
 }
 
-impl<'a> VoiceQuery<'a> {
-    pub fn new(
+impl VoiceQuery {
+    pub fn new<U: Into<String>>(
         audio_stream: Box<dyn std::io::Read + Send>,
-        user_id: &'a str,
-        mut request_info: RequestInfo,
+        user_id: U,
+        request_info: RequestInfo,
     ) -> Self {
-        request_info.set(
-            "SDK".to_string(),
-            Value::String("houndify-sdk-rust/1.0".to_string()),
-        ); // TODO: get the SDK version from manifest?
-        request_info.set("UserID".to_string(), Value::String(user_id.to_string()));
         VoiceQuery {
             audio_stream,
-            user_id,
+            user_id: user_id.into(),
             request_info,
         }
     }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    pub fn request_info(&self) -> &RequestInfo {
+        &self.request_info
+    }
+
+    pub fn request_info_mut(&mut self) -> &mut RequestInfo {
+        &mut self.request_info
+    }
+}
+
+impl std::fmt::Debug for VoiceQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("VoiceQuery")
+            .field("user_id", &self.user_id)
+            .field("request_info", &self.request_info)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod query_tests {
+    use super::*;
+    use crate::test_strategies;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn prop_request_info_round_trip(map in test_strategies::json_map()) {
+            let json = RequestInfo::from(map.clone()).serialize().unwrap();
+            prop_assert_eq!(serde_json::from_str::<Map<String, Value>>(&json).unwrap(), map);
+        }
+
+        #[test]
+        fn prop_apply_defaults_keeps_set_fields(
+            map in test_strategies::json_map(),
+            defaults in test_strategies::json_map(),
+        ) {
+            let mut request_info = RequestInfo::from(map.clone());
+            request_info.apply_defaults(&RequestInfo::from(defaults.clone()));
+            for (k, v) in &map {
+                if !v.is_object() {
+                    prop_assert_eq!(request_info.get(k), Some(v));
+                }
+            }
+            for k in defaults.keys() {
+                prop_assert!(request_info.contains(k));
+            }
+        }
+    }
 }
@@ -0,0 +1,268 @@
+use crate::error::RateLimitedError;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Called with the user id and the expected wait whenever a query has to wait for the rate
+/// limiter
+pub type WaitCallback = Box<dyn Fn(&str, Duration) + Send + Sync>;
+
+/// Number of per-user buckets kept before full ones are swept out
+const MIN_SWEEP_LEN: usize = 64;
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    per_second: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_second: f64, burst: u32, now: Instant) -> Self {
+        TokenBucket {
+            per_second,
+            capacity: f64::from(burst.max(1)),
+            tokens: f64::from(burst.max(1)),
+            updated: now,
+        }
+    }
+
+    /// Whether the bucket will have refilled completely by `now`, making it no different from
+    /// a new one
+    fn is_full_at(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * self.per_second >= self.capacity
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// How long until a token is available
+    fn wait(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.per_second)
+        }
+    }
+}
+
+#[derive(Default)]
+struct Buckets {
+    global: Option<TokenBucket>,
+    users: HashMap<String, TokenBucket>,
+    /// Sweep full per-user buckets once there are this many
+    sweep_len: usize,
+}
+
+impl Buckets {
+    /// Drop the per-user buckets that have refilled, so that idle users do not accumulate.
+    /// Sweeps happen each time the number of buckets doubles, which keeps them amortized O(1).
+    fn sweep(&mut self, now: Instant) {
+        if self.users.len() < self.sweep_len.max(MIN_SWEEP_LEN) {
+            return;
+        }
+        self.users.retain(|_, bucket| !bucket.is_full_at(now));
+        self.sweep_len = self.users.len() * 2;
+    }
+}
+
+/// Token bucket rate limiter for queries, with an optional global limit and an optional limit
+/// per user id. A query takes one token from each bucket that applies to it.
+pub struct RateLimiter {
+    global: Option<(f64, u32)>,
+    per_user: Option<(f64, u32)>,
+    buckets: Mutex<Buckets>,
+    on_wait: Option<WaitCallback>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        RateLimiter {
+            global: None,
+            per_user: None,
+            buckets: Mutex::new(Buckets::default()),
+            on_wait: None,
+        }
+    }
+
+    /// Allow `per_second` queries per second across all users, in bursts of up to `burst`
+    ///
+    /// # Panics
+    ///
+    /// If `per_second` is not a positive, finite number
+    pub fn global(mut self, per_second: f64, burst: u32) -> Self {
+        check_rate(per_second);
+        self.global = Some((per_second, burst));
+        self
+    }
+
+    /// Allow `per_second` queries per second for each user id, in bursts of up to `burst`
+    ///
+    /// # Panics
+    ///
+    /// If `per_second` is not a positive, finite number
+    pub fn per_user(mut self, per_second: f64, burst: u32) -> Self {
+        check_rate(per_second);
+        self.per_user = Some((per_second, burst));
+        self
+    }
+
+    /// Report back-pressure: `f` is called before a blocking acquire has to wait
+    pub fn on_wait<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str, Duration) + Send + Sync + 'static,
+    {
+        self.on_wait = Some(Box::new(f));
+        self
+    }
+
+    /// Take a token for `user_id` if one is available now, otherwise fail with the time until
+    /// one will be
+    pub fn try_acquire(&self, user_id: &str) -> std::result::Result<(), RateLimitedError> {
+        self.try_acquire_at(user_id, Instant::now())
+    }
+
+    /// Take a token for `user_id`, sleeping until one is available. Returns how long it waited.
+    pub fn acquire(&self, user_id: &str) -> Duration {
+        let mut waited = Duration::from_secs(0);
+        loop {
+            match self.try_acquire(user_id) {
+                Ok(()) => return waited,
+                Err(e) => {
+                    if let Some(f) = &self.on_wait {
+                        f(user_id, e.wait());
+                    }
+                    thread::sleep(e.wait());
+                    waited += e.wait();
+                }
+            }
+        }
+    }
+
+    fn try_acquire_at(
+        &self,
+        user_id: &str,
+        now: Instant,
+    ) -> std::result::Result<(), RateLimitedError> {
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.sweep(now);
+        let Buckets { global, users, .. } = &mut *buckets;
+
+        let mut applicable = vec![];
+        if let Some((per_second, burst)) = self.global {
+            applicable.push(global.get_or_insert_with(|| TokenBucket::new(per_second, burst, now)));
+        }
+        if let Some((per_second, burst)) = self.per_user {
+            applicable.push(
+                users
+                    .entry(user_id.to_string())
+                    .or_insert_with(|| TokenBucket::new(per_second, burst, now)),
+            );
+        }
+
+        for bucket in applicable.iter_mut() {
+            bucket.refill(now);
+        }
+        let wait = applicable
+            .iter()
+            .map(|b| b.wait())
+            .max()
+            .unwrap_or_default();
+        if wait > Duration::from_secs(0) {
+            return Err(RateLimitedError::new(wait));
+        }
+        for bucket in applicable.iter_mut() {
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+fn check_rate(per_second: f64) {
+    assert!(
+        per_second > 0.0 && per_second.is_finite(),
+        "rate limit must be a positive number of queries per second, not {}",
+        per_second
+    );
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for RateLimiter {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("global", &self.global)
+            .field("per_user", &self.per_user)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use super::*;
+
+    #[test]
+    fn test_global_limit() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new().global(2.0, 2);
+        assert!(limiter.try_acquire_at("alice", start).is_ok());
+        assert!(limiter.try_acquire_at("bob", start).is_ok());
+        let err = limiter.try_acquire_at("alice", start).unwrap_err();
+        assert_eq!(err.wait(), Duration::from_millis(500));
+        assert!(limiter
+            .try_acquire_at("alice", start + Duration::from_millis(500))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_per_user_limit() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new().global(100.0, 100).per_user(1.0, 1);
+        assert!(limiter.try_acquire_at("alice", start).is_ok());
+        assert!(limiter.try_acquire_at("bob", start).is_ok());
+        assert!(limiter.try_acquire_at("alice", start).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_rate_panics() {
+        RateLimiter::new().per_user(0.0, 1);
+    }
+
+    #[test]
+    fn test_idle_users_are_evicted() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new().per_user(1.0, 1);
+        for i in 0..MIN_SWEEP_LEN {
+            let user_id = format!("user{}", i);
+            assert!(limiter.try_acquire_at(&user_id, start).is_ok());
+        }
+        let later = start + Duration::from_secs(1);
+        assert!(limiter.try_acquire_at("user0", later).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().users.len(), 1);
+        assert!(limiter.try_acquire_at("user0", later).is_err());
+    }
+
+    #[test]
+    fn test_acquire_blocks_and_reports() {
+        let limiter = RateLimiter::new()
+            .per_user(20.0, 1)
+            .on_wait(|user_id, wait| {
+                assert_eq!(user_id, "alice");
+                assert!(wait <= Duration::from_millis(50));
+            });
+        assert_eq!(limiter.acquire("alice"), Duration::from_secs(0));
+        assert!(limiter.acquire("alice") > Duration::from_secs(0));
+    }
+}
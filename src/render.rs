@@ -0,0 +1,200 @@
+use crate::response::TemplateData;
+use serde_json::{Map, Value};
+
+/// Render a template as plain text suitable for a terminal
+pub fn render_text(template: &TemplateData) -> String {
+    let mut out = String::new();
+    write_text(template, &mut out);
+    out.trim_end().to_string()
+}
+
+/// Render a template as a minimal, unstyled HTML fragment
+pub fn render_html(template: &TemplateData) -> String {
+    let mut out = String::new();
+    write_html(template, &mut out);
+    out
+}
+
+/// Render an entry of "AllResults" as plain text, using its template if it has one and its
+/// "WrittenResponse" otherwise
+pub fn render_result_text(result: &Map<String, Value>) -> Option<String> {
+    match TemplateData::from_result(result) {
+        Some(template) if !matches!(template, TemplateData::Unknown(_)) => {
+            Some(render_text(&template))
+        }
+        _ => written_response(result).map(String::from),
+    }
+}
+
+/// Render an entry of "AllResults" as an HTML fragment, using its template if it has one and
+/// its "WrittenResponse" otherwise
+pub fn render_result_html(result: &Map<String, Value>) -> Option<String> {
+    match TemplateData::from_result(result) {
+        Some(template) if !matches!(template, TemplateData::Unknown(_)) => {
+            Some(render_html(&template))
+        }
+        _ => written_response(result).map(|r| format!("<p>{}</p>", escape(r))),
+    }
+}
+
+fn written_response(result: &Map<String, Value>) -> Option<&str> {
+    result.get("WrittenResponse").and_then(Value::as_str)
+}
+
+fn write_text(template: &TemplateData, out: &mut String) {
+    match template {
+        TemplateData::SimpleText(t) => {
+            push_line(out, &t.title);
+            for line in t.subtitle.iter().chain(t.body_text.iter()) {
+                push_line(out, line);
+            }
+        }
+        TemplateData::TwoColumn(t) => {
+            for line in t.title.iter().chain(t.subtitle.iter()) {
+                push_line(out, line);
+            }
+            let width = t
+                .rows
+                .iter()
+                .map(|r| r.left.chars().count())
+                .max()
+                .unwrap_or(0);
+            for row in &t.rows {
+                push_line(
+                    out,
+                    &format!("{:width$}  {}", row.left, row.right, width = width),
+                );
+            }
+        }
+        TemplateData::Image(t) => {
+            for line in t.title.iter().chain(t.caption.iter()) {
+                push_line(out, line);
+            }
+            push_line(out, &format!("[image: {}]", t.image_url));
+        }
+        TemplateData::VerticalList(t) => {
+            for item in &t.items {
+                write_text(&item.template_data, out);
+                if item.divider_below {
+                    push_line(out, "----");
+                }
+            }
+        }
+        TemplateData::Unknown(_) => {}
+    }
+}
+
+fn write_html(template: &TemplateData, out: &mut String) {
+    match template {
+        TemplateData::SimpleText(t) => {
+            out.push_str("<div>");
+            out.push_str(&format!("<h3>{}</h3>", escape(&t.title)));
+            for line in t.subtitle.iter().chain(t.body_text.iter()) {
+                out.push_str(&format!("<p>{}</p>", escape(line)));
+            }
+            out.push_str("</div>");
+        }
+        TemplateData::TwoColumn(t) => {
+            out.push_str("<div>");
+            if let Some(title) = &t.title {
+                out.push_str(&format!("<h3>{}</h3>", escape(title)));
+            }
+            if let Some(subtitle) = &t.subtitle {
+                out.push_str(&format!("<p>{}</p>", escape(subtitle)));
+            }
+            out.push_str("<table>");
+            for row in &t.rows {
+                out.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td></tr>",
+                    escape(&row.left),
+                    escape(&row.right)
+                ));
+            }
+            out.push_str("</table></div>");
+        }
+        TemplateData::Image(t) => {
+            out.push_str("<figure>");
+            if let Some(title) = &t.title {
+                out.push_str(&format!("<h3>{}</h3>", escape(title)));
+            }
+            out.push_str(&format!(
+                "<img src=\"{}\" alt=\"{}\">",
+                escape(&t.image_url),
+                escape(t.caption.as_deref().or(t.title.as_deref()).unwrap_or(""))
+            ));
+            if let Some(caption) = &t.caption {
+                out.push_str(&format!("<figcaption>{}</figcaption>", escape(caption)));
+            }
+            out.push_str("</figure>");
+        }
+        TemplateData::VerticalList(t) => {
+            out.push_str("<div>");
+            for item in &t.items {
+                write_html(&item.template_data, out);
+                if item.divider_below {
+                    out.push_str("<hr>");
+                }
+            }
+            out.push_str("</div>");
+        }
+        TemplateData::Unknown(_) => {}
+    }
+}
+
+fn push_line(out: &mut String, line: &str) {
+    out.push_str(line);
+    out.push('\n');
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod render_tests {
+    use super::*;
+    use crate::response::HoundServerResponse;
+    use serde_json::json;
+
+    fn sample_result() -> Map<String, Value> {
+        let file = std::fs::File::open("tests/sample_result.json").unwrap();
+        let hsr: HoundServerResponse = serde_json::from_reader(file).unwrap();
+        hsr.all_results.unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_render_sample_result() {
+        let result = sample_result();
+        assert_eq!(
+            render_result_text(&result).unwrap(),
+            "8:57 pm\nToronto, Canada"
+        );
+        assert_eq!(
+            render_result_html(&result).unwrap(),
+            "<div><div><h3>8:57 pm</h3><p>Toronto, Canada</p></div></div>"
+        );
+    }
+
+    #[test]
+    fn test_render_two_column() {
+        let template = TemplateData::from_value(&json!({
+            "TemplateName": "TwoColumnTemplate",
+            "Title": "Stats",
+            "Rows": [{"Left": "Height", "Right": "1.8m"}, {"Left": "Age", "Right": "<30"}]
+        }));
+        assert_eq!(render_text(&template), "Stats\nHeight  1.8m\nAge     <30");
+        assert!(render_html(&template).contains("<td>&lt;30</td>"));
+    }
+
+    #[test]
+    fn test_render_falls_back_to_written_response() {
+        let result = json!({"ViewType": ["Native"], "WrittenResponse": "1 + 1 = 2"});
+        let result = result.as_object().unwrap();
+        assert_eq!(render_result_text(result).unwrap(), "1 + 1 = 2");
+        assert_eq!(render_result_html(result).unwrap(), "<p>1 + 1 = 2</p>");
+    }
+}
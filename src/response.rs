@@ -1,8 +1,14 @@
-use serde::{Deserialize, Serialize};
-use serde_json;
+use crate::client_action::ClientActionOutcome;
+use serde::de::{DeserializeOwned, Error as _, MapAccess, Visitor};
+use serde::ser::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::value::RawValue;
 use serde_json::{Map, Value};
+use std::fmt::{self, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::io::Read;
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "PascalCase")]
 pub struct DomainUsage {
     /// This field gives the title of a domain that was used by this query.
@@ -17,7 +23,7 @@ pub struct DomainUsage {
 }
 
 /// Reference: https://docs.houndify.com/reference/BuildInfo
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "PascalCase")]
 pub struct BuildInfo {
     /// This is the user ID that built this server executable.
@@ -47,8 +53,7 @@ pub struct BuildInfo {
     pub variant: Option<String>,
 }
 
-
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "PascalCase")]
 pub struct DisambiguationChoice {
     /// This gives the transcription that led to the corresponding result in "AllResults". Sometimes, the different results in "AllResults" will come from different transcriptions of the audio and sometimes the different results will come from different parses of the same transcription. It is provided here so it can be presented to the user as one of the things we think he or she said.
@@ -61,7 +66,7 @@ pub struct DisambiguationChoice {
     pub fixed_transcription: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "PascalCase")]
 pub struct Disambiguation {
     /// This field tells the client how many of the results from "AllResults" should be shown to the user, for the user to choose among. If it is 1, then the server is suggesting not giving the user a choice and instead just showing the first result in "AllResults". If it is greater than one, the server is suggesting showing that many results and asking the user to choose among them. The elements of "AllResults" are ordered in preference from most prefered to least, so if this field has the value 3, the server is suggesting using the first three results from "AllResults".
@@ -71,8 +76,227 @@ pub struct Disambiguation {
     pub choice_data: DisambiguationChoice,
 }
 
+/// Reference: https://docs.houndify.com/reference/SimpleText
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct SimpleTextTemplate {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub body_text: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct TwoColumnRow {
+    pub left: String,
+    pub right: String,
+}
+
+/// Reference: https://docs.houndify.com/reference/TwoColumnTemplate
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct TwoColumnTemplate {
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    #[serde(default)]
+    pub rows: Vec<TwoColumnRow>,
+}
+
+/// Reference: https://docs.houndify.com/reference/ImageTemplate
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageTemplate {
+    pub title: Option<String>,
+    #[serde(rename = "ImageURL")]
+    pub image_url: String,
+    pub caption: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct TemplateListItem {
+    #[serde(default)]
+    pub divider_below: bool,
+    pub template_data: TemplateData,
+}
+
+/// Reference: https://docs.houndify.com/reference/VerticalTemplateList
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct VerticalTemplateList {
+    #[serde(default)]
+    pub items: Vec<TemplateListItem>,
+}
+
+/// The "TemplateData" of a result, selected by its "TemplateName".
+///
+/// Templates this SDK does not model, or which fail to decode, are kept as `Unknown`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateData {
+    SimpleText(SimpleTextTemplate),
+    TwoColumn(TwoColumnTemplate),
+    Image(ImageTemplate),
+    VerticalList(VerticalTemplateList),
+    Unknown(Map<String, Value>),
+}
+
+impl TemplateData {
+    /// The "TemplateData" of an entry of "AllResults", if its "ViewType" includes "Template"
+    pub fn from_result(result: &Map<String, Value>) -> Option<TemplateData> {
+        let view_types = result.get("ViewType").and_then(Value::as_array)?;
+        if !view_types.iter().any(|v| v == "Template") {
+            return None;
+        }
+        result.get("TemplateData").map(TemplateData::from_value)
+    }
+
+    pub fn from_value(v: &Value) -> TemplateData {
+        let decoded = match v.get("TemplateName").and_then(Value::as_str) {
+            Some("SimpleText") => serde_json::from_value(v.clone()).map(TemplateData::SimpleText),
+            Some("TwoColumnTemplate") => {
+                serde_json::from_value(v.clone()).map(TemplateData::TwoColumn)
+            }
+            Some("ImageTemplate") => serde_json::from_value(v.clone()).map(TemplateData::Image),
+            Some("VerticalTemplateList") => {
+                serde_json::from_value(v.clone()).map(TemplateData::VerticalList)
+            }
+            _ => return TemplateData::Unknown(v.as_object().cloned().unwrap_or_default()),
+        };
+        decoded
+            .unwrap_or_else(|_| TemplateData::Unknown(v.as_object().cloned().unwrap_or_default()))
+    }
+
+    pub fn template_name(&self) -> Option<&str> {
+        match self {
+            TemplateData::SimpleText(_) => Some("SimpleText"),
+            TemplateData::TwoColumn(_) => Some("TwoColumnTemplate"),
+            TemplateData::Image(_) => Some("ImageTemplate"),
+            TemplateData::VerticalList(_) => Some("VerticalTemplateList"),
+            TemplateData::Unknown(m) => m.get("TemplateName").and_then(Value::as_str),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TemplateData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(TemplateData::from_value(&Value::deserialize(deserializer)?))
+    }
+}
+
+impl Serialize for TemplateData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let v = match self {
+            TemplateData::SimpleText(t) => serde_json::to_value(t),
+            TemplateData::TwoColumn(t) => serde_json::to_value(t),
+            TemplateData::Image(t) => serde_json::to_value(t),
+            TemplateData::VerticalList(t) => serde_json::to_value(t),
+            TemplateData::Unknown(m) => return m.serialize(serializer),
+        };
+        let mut map = match v.map_err(S::Error::custom)? {
+            Value::Object(m) => m,
+            _ => Map::new(),
+        };
+        if let Some(name) = self.template_name() {
+            map.insert("TemplateName".to_string(), Value::String(name.to_string()));
+        }
+        map.serialize(serializer)
+    }
+}
+
+/// Defines an enumeration of the JSON strings a response field may hold, with an `Unknown`
+/// variant keeping any other string so it survives serialization.
+///
+/// Known strings always decode to their own variant. Values are compared and hashed by their
+/// string, so an `Unknown` holding a known string still equals that variant.
+macro_rules! string_enum {
+    ($(#[$meta:meta])* $name:ident { $($(#[$vmeta:meta])* $variant:ident => $value:expr,)+ }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone)]
+        pub enum $name {
+            $($(#[$vmeta])* $variant,)+
+            /// A value this SDK does not know about
+            Unknown(String),
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $($name::$variant => $value,)+
+                    $name::Unknown(s) => s,
+                }
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.as_str() == other.as_str()
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl Hash for $name {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.as_str().hash(state)
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(s: &str) -> Self {
+                match s {
+                    $($value => $name::$variant,)+
+                    _ => $name::Unknown(s.to_string()),
+                }
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(s: String) -> Self {
+                match s.as_str() {
+                    $($value => $name::$variant,)+
+                    _ => $name::Unknown(s),
+                }
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                Ok($name::from(String::deserialize(deserializer)?))
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+    };
+}
+
+string_enum! {
+    /// The "Status" of a response
+    Status {
+        Ok => "OK",
+        Error => "Error",
+    }
+}
+
+string_enum! {
+    /// Whether a dual server answered with its local or a remote engine
+    LocalOrRemote {
+        Local => "Local",
+        Remote => "Remote",
+    }
+}
+
 /// Reference: https://docs.houndify.com/reference/HoundServer
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "PascalCase")]
 pub struct HoundServerResponse {
     /// This field is a marker to verify that this is a value in this format.
@@ -82,7 +306,7 @@ pub struct HoundServerResponse {
     pub format_version: String,
 
     /// This field uses only a fixed, finite number of JSON strings to encode an enumeration.
-    pub status: String,
+    pub status: Status,
 
     /// If the "Status" was "Error", then this field will be present to give a human-readable explanation of what went wrong.
     pub error_message: Option<String>,
@@ -128,22 +352,513 @@ pub struct HoundServerResponse {
     pub cpu_time: Option<f64>,
 
     /// If the Hound server returning this JSON is a dual server that can either service requests locally or use a remote server to service them, then it can set this field to indicate whether the result it is sending came from its local engine or a remote engine.
-    pub local_or_remote: Option<String>,
+    pub local_or_remote: Option<LocalOrRemote>,
 
     /// If the Hound server returning this JSON is a dual server that can either service requests locally or use a remote server to service them, then it can set this field to indicate why it chose the result from its local engine or a remote engine.
     pub local_or_remote_reason: Option<String>,
+
+    /// Top level fields this SDK does not model, so they are not lost when the response is
+    /// serialized again.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+
+    /// The body of the response as received, if the client was asked to keep it
+    #[serde(skip)]
+    pub raw: Option<Vec<u8>>,
+
+    /// The client actions run for the results of this response, in the order of "AllResults"
+    #[serde(skip)]
+    pub client_action_outcomes: Vec<ClientActionOutcome>,
+}
+
+/// Decoded with a visitor rather than derived, because `#[serde(flatten)]` makes serde buffer
+/// the whole object before decoding any field. Unknown keys are collected into `extra` as they
+/// are read.
+impl<'de> Deserialize<'de> for HoundServerResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(ResponseVisitor)
+    }
+}
+
+struct ResponseVisitor;
+
+impl<'de> Visitor<'de> for ResponseVisitor {
+    type Value = HoundServerResponse;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("a Houndify response object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut format = None;
+        let mut format_version = None;
+        let mut status = None;
+        let mut query_id = None;
+        let mut response = HoundServerResponse {
+            format: String::new(),
+            format_version: String::new(),
+            status: Status::Ok,
+            error_message: None,
+            num_to_return: None,
+            all_results: None,
+            disambiguation: None,
+            results_are_final: None,
+            domain_usage: None,
+            build_info: None,
+            query_id: String::new(),
+            server_generated_id: None,
+            audio_length: None,
+            real_speech_time: None,
+            cpu_speech_time: None,
+            real_time: None,
+            cpu_time: None,
+            local_or_remote: None,
+            local_or_remote_reason: None,
+            extra: Map::new(),
+            raw: None,
+            client_action_outcomes: vec![],
+        };
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "Format" => format = Some(map.next_value()?),
+                "FormatVersion" => format_version = Some(map.next_value()?),
+                "Status" => status = Some(map.next_value()?),
+                "ErrorMessage" => response.error_message = map.next_value()?,
+                "NumToReturn" => response.num_to_return = map.next_value()?,
+                "AllResults" => response.all_results = map.next_value()?,
+                "Disambiguation" => response.disambiguation = map.next_value()?,
+                "ResultsAreFinal" => response.results_are_final = map.next_value()?,
+                "DomainUsage" => response.domain_usage = map.next_value()?,
+                "BuildInfo" => response.build_info = map.next_value()?,
+                "QueryID" => query_id = Some(map.next_value()?),
+                "ServerGeneratedId" => response.server_generated_id = map.next_value()?,
+                "AudioLength" => response.audio_length = map.next_value()?,
+                "RealSpeechTime" => response.real_speech_time = map.next_value()?,
+                "CpuSpeechTime" => response.cpu_speech_time = map.next_value()?,
+                "RealTime" => response.real_time = map.next_value()?,
+                "CpuTime" => response.cpu_time = map.next_value()?,
+                "LocalOrRemote" => response.local_or_remote = map.next_value()?,
+                "LocalOrRemoteReason" => response.local_or_remote_reason = map.next_value()?,
+                _ => {
+                    response.extra.insert(key, map.next_value()?);
+                }
+            }
+        }
+        response.format = format.ok_or_else(|| A::Error::missing_field("Format"))?;
+        response.format_version =
+            format_version.ok_or_else(|| A::Error::missing_field("FormatVersion"))?;
+        response.status = status.ok_or_else(|| A::Error::missing_field("Status"))?;
+        response.query_id = query_id.ok_or_else(|| A::Error::missing_field("QueryID"))?;
+        Ok(response)
+    }
+}
+
+/// A one line summary: the status, the query ID, and the written response of the first result
+/// or the error message. The alternate form, `{:#}`, is the response as pretty-printed JSON.
+impl Display for HoundServerResponse {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if f.alternate() {
+            let json = serde_json::to_string_pretty(self).map_err(|_| fmt::Error)?;
+            return f.write_str(&json);
+        }
+        write!(f, "{} [{}]", self.status, self.query_id)?;
+        match (&self.error_message, self.written_response()) {
+            (Some(message), _) => write!(f, ": {}", message),
+            (None, Some(written_response)) => write!(f, ": {}", written_response),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Time spent on a query, from the timing fields of a `HoundServerResponse`. All times are in
+/// seconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProcessingStats {
+    pub audio_length: Option<f64>,
+    pub real_speech_time: Option<f64>,
+    pub cpu_speech_time: Option<f64>,
+    pub real_time: Option<f64>,
+    pub cpu_time: Option<f64>,
+}
+
+impl ProcessingStats {
+    /// Real time spent processing speech per second of audio
+    pub fn speech_real_time_factor(&self) -> Option<f64> {
+        match (self.real_speech_time, self.audio_length) {
+            (Some(t), Some(len)) if len > 0.0 => Some(t / len),
+            _ => None,
+        }
+    }
+}
+
+impl HoundServerResponse {
+    /// Decode a response as it is read, without buffering the whole text
+    pub fn from_reader<R: Read>(reader: R) -> serde_json::Result<HoundServerResponse> {
+        serde_json::from_reader(std::io::BufReader::new(reader))
+    }
+
+    /// Whether "Status" is "OK"
+    pub fn is_ok(&self) -> bool {
+        self.status == Status::Ok
+    }
+
+    /// The first entry of "AllResults", which the server ranks best
+    pub fn best_result(&self) -> Option<&Map<String, Value>> {
+        self.all_results
+            .as_ref()
+            .and_then(|results| results.first())
+    }
+
+    fn best_result_str(&self, field: &str) -> Option<&str> {
+        self.best_result()
+            .and_then(|result| result.get(field))
+            .and_then(Value::as_str)
+    }
+
+    /// "SpokenResponse" of the best result
+    pub fn spoken_response(&self) -> Option<&str> {
+        self.best_result_str("SpokenResponse")
+    }
+
+    /// "WrittenResponse" of the best result
+    pub fn written_response(&self) -> Option<&str> {
+        self.best_result_str("WrittenResponse")
+    }
+
+    /// "CommandKind" of the best result
+    pub fn command_kind(&self) -> Option<&str> {
+        self.best_result_str("CommandKind")
+    }
+
+    /// What the server heard for the best result of a voice query
+    pub fn transcription(&self) -> Option<&str> {
+        self.disambiguation
+            .as_ref()
+            .and_then(|d| d.get("ChoiceData"))
+            .and_then(|choices| choices.get(0))
+            .and_then(|choice| choice.get("Transcription"))
+            .and_then(Value::as_str)
+    }
+
+    /// Credits used across all domains, if the response reports them
+    pub fn total_credits(&self) -> Option<f64> {
+        self.domain_usage
+            .as_ref()
+            .map(|usage| usage.iter().map(|u| u.credits_used).sum())
+    }
+
+    /// The timing fields of the response, if any are present
+    pub fn processing_stats(&self) -> Option<ProcessingStats> {
+        let stats = ProcessingStats {
+            audio_length: self.audio_length,
+            real_speech_time: self.real_speech_time,
+            cpu_speech_time: self.cpu_speech_time,
+            real_time: self.real_time,
+            cpu_time: self.cpu_time,
+        };
+        if stats == ProcessingStats::default() {
+            None
+        } else {
+            Some(stats)
+        }
+    }
+}
+
+/// A response whose envelope fields are decoded up front, while each entry of "AllResults"
+/// is kept as raw JSON and only decoded when asked for.
+#[derive(Debug, Clone)]
+pub struct LazyHoundServerResponse {
+    envelope: HoundServerResponse,
+    /// `None` if the response has no "AllResults"
+    results: Option<Vec<Box<RawValue>>>,
+}
+
+impl LazyHoundServerResponse {
+    /// Decode a response as it is read, without buffering the whole text
+    pub fn from_reader<R: Read>(reader: R) -> serde_json::Result<LazyHoundServerResponse> {
+        serde_json::from_reader(std::io::BufReader::new(reader))
+    }
+
+    /// Every field of the response except "AllResults", which is `None`
+    pub fn envelope(&self) -> &HoundServerResponse {
+        &self.envelope
+    }
+
+    /// Number of entries in "AllResults"
+    pub fn len(&self) -> usize {
+        self.results().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results().is_empty()
+    }
+
+    fn results(&self) -> &[Box<RawValue>] {
+        self.results.as_deref().unwrap_or_default()
+    }
+
+    /// The undecoded JSON of the `i`th entry of "AllResults"
+    pub fn raw_result(&self, i: usize) -> Option<&RawValue> {
+        self.results().get(i).map(|r| r.as_ref())
+    }
+
+    /// Decode the `i`th entry of "AllResults"
+    pub fn result(&self, i: usize) -> Option<serde_json::Result<Map<String, Value>>> {
+        self.result_as(i)
+    }
+
+    /// Decode the `i`th entry of "AllResults" into any type
+    pub fn result_as<T: DeserializeOwned>(&self, i: usize) -> Option<serde_json::Result<T>> {
+        self.raw_result(i).map(|r| serde_json::from_str(r.get()))
+    }
+
+    /// Decode every entry of "AllResults", giving the full response
+    pub fn into_response(self) -> serde_json::Result<HoundServerResponse> {
+        let mut response = self.envelope;
+        if let Some(results) = self.results {
+            let results = results
+                .iter()
+                .map(|r| serde_json::from_str(r.get()))
+                .collect::<serde_json::Result<_>>()?;
+            response.all_results = Some(results);
+        }
+        Ok(response)
+    }
+}
+
+/// Entries of "AllResults" are captured as raw JSON without being parsed into values. The other
+/// fields are decoded into a `serde_json::Value` and then into the envelope, so they are
+/// walked twice; they are small next to the results.
+impl<'de> Deserialize<'de> for LazyHoundServerResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(LazyVisitor)
+    }
+}
+
+struct LazyVisitor;
+
+impl<'de> Visitor<'de> for LazyVisitor {
+    type Value = LazyHoundServerResponse;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("a Houndify response object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut fields = Map::new();
+        let mut results = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "AllResults" {
+                results = map.next_value::<Option<Vec<Box<RawValue>>>>()?;
+            } else {
+                fields.insert(key, map.next_value()?);
+            }
+        }
+        let envelope = serde_json::from_value(Value::Object(fields)).map_err(A::Error::custom)?;
+        Ok(LazyHoundServerResponse { envelope, results })
+    }
+}
+
+#[cfg(feature = "domains")]
+impl HoundServerResponse {
+    /// Typed views of each entry of "AllResults", in order
+    pub fn domain_results(&self) -> Vec<crate::domains::DomainResult> {
+        self.all_results
+            .iter()
+            .flatten()
+            .map(crate::domains::DomainResult::from_result)
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod response_tests {
     use super::*;
-    use serde_json;
+    use crate::test_strategies;
+    use proptest::prelude::*;
+
+    fn round_trip<T: Serialize + DeserializeOwned>(v: &T) -> T {
+        serde_json::from_str(&serde_json::to_string(v).unwrap()).unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn prop_hound_server_response_round_trip(
+            response in test_strategies::hound_server_response()
+        ) {
+            prop_assert_eq!(&round_trip(&response), &response);
+            let lazy: LazyHoundServerResponse =
+                serde_json::from_str(&serde_json::to_string(&response).unwrap()).unwrap();
+            prop_assert_eq!(&lazy.into_response().unwrap(), &response);
+        }
+
+        #[test]
+        fn prop_template_data_round_trip(template in test_strategies::template_data()) {
+            prop_assert_eq!(&round_trip(&template), &template);
+        }
+
+        #[test]
+        fn prop_field_types_round_trip(
+            status in test_strategies::status(),
+            local_or_remote in test_strategies::local_or_remote(),
+            domain_usage in test_strategies::domain_usage(),
+            build_info in test_strategies::build_info(),
+            disambiguation in test_strategies::disambiguation(),
+        ) {
+            prop_assert_eq!(&round_trip(&status), &status);
+            prop_assert_eq!(&round_trip(&local_or_remote), &local_or_remote);
+            prop_assert_eq!(&round_trip(&domain_usage), &domain_usage);
+            prop_assert_eq!(&round_trip(&build_info), &build_info);
+            prop_assert_eq!(&round_trip(&disambiguation), &disambiguation);
+        }
+    }
 
     #[test]
     fn test_hound_server_response_parse() {
         let file = std::fs::File::open("tests/sample_result.json").unwrap();
         let hsr: HoundServerResponse = serde_json::from_reader(file).unwrap();
-        println!("{:#?}", hsr);
-        assert!(true);
+        assert_eq!(hsr.format, "SoundHoundVoiceSearchResult");
+        assert_eq!(hsr.status, Status::Ok);
+        assert_eq!(round_trip(&hsr), hsr);
+    }
+
+    #[test]
+    fn test_string_enums() {
+        let status: Status = serde_json::from_str("\"OK\"").unwrap();
+        assert_eq!(status, Status::Ok);
+        let status: Status = serde_json::from_str("\"Throttled\"").unwrap();
+        assert_eq!(status, Status::from("Throttled"));
+        assert!(matches!(&status, Status::Unknown(v) if v == "Throttled"));
+        assert_eq!(serde_json::to_string(&status).unwrap(), "\"Throttled\"");
+        assert_eq!(Status::from("OK".to_string()), Status::Ok);
+        assert!(matches!(
+            serde_json::from_str::<Status>("\"Error\"").unwrap(),
+            Status::Error
+        ));
+        assert_eq!(Status::Unknown("OK".to_string()), Status::Ok);
+        let mut seen = std::collections::HashSet::new();
+        seen.insert(Status::Unknown("Error".to_string()));
+        assert!(seen.contains(&Status::Error));
+        assert_eq!(Status::Error.to_string(), "Error");
+
+        let where_from: LocalOrRemote = serde_json::from_str("\"Remote\"").unwrap();
+        assert_eq!(where_from, LocalOrRemote::Remote);
+        assert_eq!(LocalOrRemote::from("Cache").as_str(), "Cache");
+    }
+
+    #[test]
+    fn test_accessors() {
+        let file = std::fs::File::open("tests/sample_result.json").unwrap();
+        let hsr = HoundServerResponse::from_reader(file).unwrap();
+        assert!(hsr.is_ok());
+        assert_eq!(hsr.command_kind(), Some("InformationCommand"));
+        assert_eq!(
+            hsr.written_response(),
+            hsr.best_result().unwrap()["WrittenResponse"].as_str()
+        );
+        assert!(hsr.spoken_response().is_some());
+        assert_eq!(hsr.transcription(), Some("what time is it in toronto"));
+        assert!(hsr.total_credits().is_some());
+        let stats = hsr.processing_stats().unwrap();
+        assert_eq!(stats.real_time, Some(1.456));
+        assert_eq!(stats.cpu_time, None);
+        assert!(stats.speech_real_time_factor().unwrap() < 1.0);
+
+        let empty: HoundServerResponse = serde_json::from_value(serde_json::json!({
+            "Format": "SoundHoundVoiceSearchResult",
+            "FormatVersion": "1.0",
+            "Status": "Error",
+            "ErrorMessage": "Bad audio",
+            "QueryID": "deadbeef",
+        }))
+        .unwrap();
+        assert!(!empty.is_ok());
+        assert!(empty.best_result().is_none());
+        assert!(empty.written_response().is_none());
+        assert!(empty.transcription().is_none());
+        assert!(empty.total_credits().is_none());
+        assert!(empty.processing_stats().is_none());
+        assert_eq!(empty.to_string(), "Error [deadbeef]: Bad audio");
+    }
+
+    #[test]
+    fn test_extra_fields_and_display() {
+        let json = serde_json::json!({
+            "Format": "SoundHoundVoiceSearchResult",
+            "FormatVersion": "1.0",
+            "Status": "OK",
+            "QueryID": "deadbeef",
+            "AllResults": [{"CommandKind": "InformationCommand", "WrittenResponse": "2"}],
+            "NewTopLevelField": {"Added": "later"},
+        });
+        let response: HoundServerResponse = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(response.extra["NewTopLevelField"]["Added"], "later");
+        assert_eq!(
+            serde_json::to_value(&response).unwrap()["NewTopLevelField"],
+            json["NewTopLevelField"]
+        );
+        assert_eq!(response.to_string(), "OK [deadbeef]: 2");
+        let pretty: Value = serde_json::from_str(&format!("{:#}", response)).unwrap();
+        assert_eq!(pretty["NewTopLevelField"], json["NewTopLevelField"]);
+
+        let file = std::fs::File::open("tests/sample_result.json").unwrap();
+        let hsr = HoundServerResponse::from_reader(file).unwrap();
+        assert!(hsr.to_string().starts_with("OK ["));
+    }
+
+    #[test]
+    fn test_response_fields() {
+        let mut json = serde_json::json!({
+            "Format": "SoundHoundVoiceSearchResult",
+            "FormatVersion": "1.0",
+            "Status": "OK",
+            "QueryID": "deadbeef",
+            "ErrorMessage": null,
+            "RealTime": 0.25,
+        });
+        let response: HoundServerResponse = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(response.error_message, None);
+        assert_eq!(response.real_time, Some(0.25));
+        assert!(response.extra.is_empty());
+
+        json.as_object_mut().unwrap().remove("QueryID");
+        let err = serde_json::from_value::<HoundServerResponse>(json).unwrap_err();
+        assert_eq!(err.to_string(), "missing field `QueryID`");
+    }
+
+    #[test]
+    fn test_lazy_response() {
+        let file = std::fs::File::open("tests/sample_result.json").unwrap();
+        let lazy = LazyHoundServerResponse::from_reader(file).unwrap();
+        let file = std::fs::File::open("tests/sample_result.json").unwrap();
+        let full = HoundServerResponse::from_reader(file).unwrap();
+
+        assert_eq!(lazy.envelope().query_id, full.query_id);
+        assert!(lazy.envelope().all_results.is_none());
+        let all_results = full.all_results.as_ref().unwrap();
+        assert_eq!(lazy.len(), all_results.len());
+        assert_eq!(&lazy.result(0).unwrap().unwrap(), &all_results[0]);
+        assert!(lazy.result(lazy.len()).is_none());
+
+        let raw: Value = serde_json::from_str(lazy.raw_result(0).unwrap().get()).unwrap();
+        assert_eq!(raw["CommandKind"], all_results[0]["CommandKind"]);
+        assert_eq!(
+            serde_json::to_value(lazy.into_response().unwrap()).unwrap(),
+            serde_json::to_value(full).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_lazy_empty_results() {
+        let envelope = r#""Format":"SoundHoundVoiceSearchResult","FormatVersion":"1.0","Status":"OK","QueryID":"deadbeef""#;
+        let empty: LazyHoundServerResponse =
+            serde_json::from_str(&format!("{{{},\"AllResults\":[]}}", envelope)).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.into_response().unwrap().all_results, Some(vec![]));
+
+        let missing: LazyHoundServerResponse =
+            serde_json::from_str(&format!("{{{}}}", envelope)).unwrap();
+        assert!(missing.is_empty());
+        assert_eq!(missing.into_response().unwrap().all_results, None);
     }
 }
@@ -0,0 +1,133 @@
+//! A minimal HTTP server standing in for the Houndify API in unit tests
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercased
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// Extra response headers and the response body
+pub type Response = (Vec<(&'static str, String)>, Vec<u8>);
+
+pub type Handler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+/// Serve `handler`'s JSON responses on a local port until the test process exits. Returns the
+/// API URL to pass to `Client::new`.
+pub fn serve<F>(handler: F) -> String
+where
+    F: Fn(&Request) -> String + Send + Sync + 'static,
+{
+    serve_with_headers(move |request| (vec![], handler(request).into_bytes()))
+}
+
+/// Like `serve`, for handlers which also set response headers
+pub fn serve_with_headers<F>(handler: F) -> String
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let handler: Handler = Arc::new(handler);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let handler = handler.clone();
+            let stream = match stream {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+            thread::spawn(move || handle(stream, handler));
+        }
+    });
+    url
+}
+
+fn handle(mut stream: TcpStream, handler: Handler) {
+    let request = match read_request(&mut stream) {
+        Some(r) => r,
+        None => return,
+    };
+    let (headers, body) = handler(&request);
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        body.len()
+    );
+    for (k, v) in headers {
+        response.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    response.push_str("\r\n");
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.write_all(&body);
+}
+
+fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (k, v) = line.split_at(line.find(':')?);
+        headers.insert(k.to_lowercase(), v[1..].trim().to_string());
+    }
+
+    let mut body = vec![];
+    if let Some(len) = headers.get("content-length") {
+        body.resize(len.parse().ok()?, 0);
+        reader.read_exact(&mut body).ok()?;
+    } else if headers.get("transfer-encoding").map(String::as_str) == Some("chunked") {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size).ok()?;
+            let size = usize::from_str_radix(size.trim(), 16).ok()?;
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).ok()?;
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    }
+
+    Some(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+/// A successful response with a single result
+pub fn ok_response(written_response: &str, domain: &str, credits: f64) -> String {
+    serde_json::json!({
+        "Format": "SoundHoundVoiceSearchResult",
+        "FormatVersion": "1.0",
+        "Status": "OK",
+        "NumToReturn": 1,
+        "AllResults": [{
+            "CommandKind": "InformationCommand",
+            "WrittenResponse": written_response,
+            "SpokenResponse": written_response,
+        }],
+        "DomainUsage": [{"Domain": domain, "DomainUniqueID": domain, "CreditsUsed": credits}],
+        "QueryID": "deadbeef",
+    })
+    .to_string()
+}
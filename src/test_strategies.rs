@@ -0,0 +1,225 @@
+//! Proptest strategies for the JSON and response types, shared by the round trip tests
+
+use crate::response::{
+    BuildInfo, Disambiguation, DisambiguationChoice, DomainUsage, HoundServerResponse,
+    ImageTemplate, LocalOrRemote, SimpleTextTemplate, Status, TemplateData, TemplateListItem,
+    TwoColumnRow, TwoColumnTemplate, VerticalTemplateList,
+};
+use proptest::collection::{btree_map, vec};
+use proptest::option;
+use proptest::prelude::*;
+use serde_json::{Map, Number, Value};
+
+/// Field names which cannot collide with the fields `HoundServerResponse` models
+fn key() -> impl Strategy<Value = String> {
+    "X[a-z]{0,8}"
+}
+
+/// Seconds, credits and the like. These have few significant digits, so serde_json parses
+/// them back exactly.
+fn decimal() -> impl Strategy<Value = f64> {
+    (0u32..10_000_000).prop_map(|n| f64::from(n) / 1000.0)
+}
+
+fn text() -> impl Strategy<Value = String> {
+    "\\PC{0,12}"
+}
+
+pub fn json_value() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::Bool),
+        any::<i64>().prop_map(|n| Value::Number(n.into())),
+        decimal().prop_map(|n| Value::Number(Number::from_f64(n).unwrap())),
+        text().prop_map(Value::String),
+    ];
+    leaf.prop_recursive(3, 32, 4, |inner| {
+        prop_oneof![
+            vec(inner.clone(), 0..4).prop_map(Value::Array),
+            btree_map(key(), inner, 0..4).prop_map(|m| Value::Object(m.into_iter().collect())),
+        ]
+    })
+}
+
+pub fn json_map() -> impl Strategy<Value = Map<String, Value>> {
+    btree_map(key(), json_value(), 0..4).prop_map(|m| m.into_iter().collect())
+}
+
+/// Any string, with the values of the given string enumeration made likely
+fn enum_string(known: &'static [&'static str]) -> impl Strategy<Value = String> {
+    prop_oneof![
+        proptest::sample::select(known).prop_map(str::to_string),
+        text(),
+    ]
+}
+
+pub fn status() -> impl Strategy<Value = Status> {
+    enum_string(&["OK", "Error", "ok", "Throttled"]).prop_map(Status::from)
+}
+
+pub fn local_or_remote() -> impl Strategy<Value = LocalOrRemote> {
+    enum_string(&["Local", "Remote", "local", "Cache"]).prop_map(LocalOrRemote::from)
+}
+
+pub fn domain_usage() -> impl Strategy<Value = DomainUsage> {
+    (text(), text(), decimal()).prop_map(|(domain, domain_unique_id, credits_used)| DomainUsage {
+        domain,
+        domain_unique_id,
+        credits_used,
+    })
+}
+
+pub fn build_info() -> impl Strategy<Value = BuildInfo> {
+    vec(option::of(text()), 8).prop_map(|mut fields| {
+        let mut next = || fields.pop().unwrap();
+        BuildInfo {
+            user: next(),
+            date: next(),
+            machine: next(),
+            svn_revision: next(),
+            svn_branch: next(),
+            build_number: next(),
+            kind: next(),
+            variant: next(),
+        }
+    })
+}
+
+pub fn disambiguation() -> impl Strategy<Value = Disambiguation> {
+    let choice = (text(), any::<i64>(), text(), option::of(text())).prop_map(
+        |(transcription, confidence_score, formatted_transcription, fixed_transcription)| {
+            DisambiguationChoice {
+                transcription,
+                confidence_score,
+                formatted_transcription,
+                fixed_transcription,
+            }
+        },
+    );
+    (any::<i64>(), choice).prop_map(|(num_to_show, choice_data)| Disambiguation {
+        num_to_show,
+        choice_data,
+    })
+}
+
+fn simple_text() -> impl Strategy<Value = SimpleTextTemplate> {
+    (text(), option::of(text()), option::of(text())).prop_map(|(title, subtitle, body_text)| {
+        SimpleTextTemplate {
+            title,
+            subtitle,
+            body_text,
+        }
+    })
+}
+
+fn two_column() -> impl Strategy<Value = TwoColumnTemplate> {
+    let row = (text(), text()).prop_map(|(left, right)| TwoColumnRow { left, right });
+    (option::of(text()), option::of(text()), vec(row, 0..4)).prop_map(|(title, subtitle, rows)| {
+        TwoColumnTemplate {
+            title,
+            subtitle,
+            rows,
+        }
+    })
+}
+
+fn image() -> impl Strategy<Value = ImageTemplate> {
+    (option::of(text()), text(), option::of(text())).prop_map(|(title, image_url, caption)| {
+        ImageTemplate {
+            title,
+            image_url,
+            caption,
+        }
+    })
+}
+
+/// A template of a name this SDK does not model
+fn unknown_template() -> impl Strategy<Value = Map<String, Value>> {
+    (json_map(), "[a-z]{1,8}").prop_map(|(mut m, name)| {
+        m.insert("TemplateName".to_string(), Value::String(name));
+        m
+    })
+}
+
+pub fn template_data() -> impl Strategy<Value = TemplateData> {
+    let leaf = prop_oneof![
+        simple_text().prop_map(TemplateData::SimpleText),
+        two_column().prop_map(TemplateData::TwoColumn),
+        image().prop_map(TemplateData::Image),
+        unknown_template().prop_map(TemplateData::Unknown),
+    ];
+    leaf.prop_recursive(2, 8, 3, |inner| {
+        let item =
+            (any::<bool>(), inner).prop_map(|(divider_below, template_data)| TemplateListItem {
+                divider_below,
+                template_data,
+            });
+        vec(item, 0..3).prop_map(|items| TemplateData::VerticalList(VerticalTemplateList { items }))
+    })
+}
+
+pub fn hound_server_response() -> impl Strategy<Value = HoundServerResponse> {
+    let envelope = (
+        text(),
+        status(),
+        option::of(text()),
+        option::of(any::<i64>()),
+        option::of(vec(json_map(), 0..3)),
+        option::of(json_map()),
+        option::of(vec(any::<bool>(), 0..3)),
+        option::of(vec(domain_usage(), 0..3)),
+        option::of(build_info()),
+        text(),
+        option::of(text()),
+    );
+    let timing = (
+        vec(option::of(decimal()), 5),
+        option::of(local_or_remote()),
+        option::of(text()),
+        json_map(),
+    );
+    (envelope, timing).prop_map(
+        |(
+            (
+                format_version,
+                status,
+                error_message,
+                num_to_return,
+                all_results,
+                disambiguation,
+                results_are_final,
+                domain_usage,
+                build_info,
+                query_id,
+                server_generated_id,
+            ),
+            (mut times, local_or_remote, local_or_remote_reason, extra),
+        )| {
+            let mut next = || times.pop().unwrap();
+            HoundServerResponse {
+                format: "SoundHoundVoiceSearchResult".to_string(),
+                format_version,
+                status,
+                error_message,
+                num_to_return,
+                all_results,
+                disambiguation,
+                results_are_final,
+                domain_usage,
+                build_info,
+                query_id,
+                server_generated_id,
+                audio_length: next(),
+                real_speech_time: next(),
+                cpu_speech_time: next(),
+                real_time: next(),
+                cpu_time: next(),
+                local_or_remote,
+                local_or_remote_reason,
+                extra,
+                raw: None,
+                client_action_outcomes: vec![],
+            }
+        },
+    )
+}
@@ -0,0 +1,203 @@
+use crate::error::BudgetExceededError;
+use crate::response::HoundServerResponse;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Credits spent over some period
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageTotals {
+    /// Number of queries that returned a response
+    pub queries: u64,
+
+    /// Total credits used
+    pub credits: f64,
+
+    /// Credits used, keyed on domain name
+    pub by_domain: HashMap<String, f64>,
+
+    /// Credits used, keyed on user id
+    pub by_user: HashMap<String, f64>,
+}
+
+impl UsageTotals {
+    fn add(&mut self, user_id: &str, domains: &[(String, f64)]) {
+        self.queries += 1;
+        let user_credits = self.by_user.entry(user_id.to_string()).or_insert(0.0);
+        for (domain, credits) in domains {
+            self.credits += credits;
+            *user_credits += credits;
+            *self.by_domain.entry(domain.clone()).or_insert(0.0) += credits;
+        }
+    }
+}
+
+/// A point in time view of a `UsageTracker`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageSnapshot {
+    /// Usage since the tracker was created
+    pub all_time: UsageTotals,
+
+    /// Usage within the tracker's window, ending now
+    pub window: UsageTotals,
+}
+
+#[derive(Debug)]
+struct Record {
+    at: Instant,
+    user_id: String,
+    domains: Vec<(String, f64)>,
+}
+
+#[derive(Debug, Default)]
+struct UsageState {
+    all_time: UsageTotals,
+    records: VecDeque<Record>,
+}
+
+/// Accumulates the "CreditsUsed" reported in each response's "DomainUsage".
+///
+/// Credits are totalled since the tracker was created and over a sliding window. If a budget
+/// is set, queries are refused with a `BudgetExceededError` once the credits used within the
+/// window reach it.
+#[derive(Debug)]
+pub struct UsageTracker {
+    window: Duration,
+    budget: Option<f64>,
+    state: Mutex<UsageState>,
+}
+
+impl UsageTracker {
+    /// Create a tracker totalling usage over a sliding window of the given length
+    pub fn new(window: Duration) -> Self {
+        UsageTracker {
+            window,
+            budget: None,
+            state: Mutex::new(UsageState::default()),
+        }
+    }
+
+    /// Refuse queries once this many credits were used within the window
+    pub fn budget(mut self, credits: f64) -> Self {
+        self.budget = Some(credits);
+        self
+    }
+
+    /// Record the domain usage of a response to a query made on behalf of `user_id`
+    pub fn record(&self, user_id: &str, response: &HoundServerResponse) {
+        self.record_at(Instant::now(), user_id, response)
+    }
+
+    /// Fail with a `BudgetExceededError` if the budget for the current window is spent
+    pub fn check_budget(&self) -> std::result::Result<(), BudgetExceededError> {
+        self.check_budget_at(Instant::now())
+    }
+
+    pub fn snapshot(&self) -> UsageSnapshot {
+        self.snapshot_at(Instant::now())
+    }
+
+    fn record_at(&self, at: Instant, user_id: &str, response: &HoundServerResponse) {
+        let domains: Vec<(String, f64)> = response
+            .domain_usage
+            .iter()
+            .flatten()
+            .map(|usage| (usage.domain.clone(), usage.credits_used))
+            .collect();
+        let mut state = self.state.lock().unwrap();
+        state.all_time.add(user_id, &domains);
+        state.records.push_back(Record {
+            at,
+            user_id: user_id.to_string(),
+            domains,
+        });
+        self.expire(&mut state, at);
+    }
+
+    fn check_budget_at(&self, at: Instant) -> std::result::Result<(), BudgetExceededError> {
+        let budget = match self.budget {
+            Some(b) => b,
+            None => return Ok(()),
+        };
+        let spent = self.snapshot_at(at).window.credits;
+        if spent >= budget {
+            Err(BudgetExceededError::new(spent, budget))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn snapshot_at(&self, at: Instant) -> UsageSnapshot {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state, at);
+        let mut window = UsageTotals::default();
+        for record in &state.records {
+            window.add(&record.user_id, &record.domains);
+        }
+        UsageSnapshot {
+            all_time: state.all_time.clone(),
+            window,
+        }
+    }
+
+    fn expire(&self, state: &mut UsageState, now: Instant) {
+        while let Some(record) = state.records.front() {
+            if now.duration_since(record.at) < self.window {
+                break;
+            }
+            state.records.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod usage_tests {
+    use super::*;
+    use serde_json::json;
+
+    fn response(domains: &[(&str, f64)]) -> HoundServerResponse {
+        let usage: Vec<_> = domains
+            .iter()
+            .map(|(d, c)| json!({"Domain": d, "DomainUniqueID": d, "CreditsUsed": c}))
+            .collect();
+        serde_json::from_value(json!({
+            "Format": "SoundHoundVoiceSearchResult",
+            "FormatVersion": "1.0",
+            "Status": "OK",
+            "QueryID": "deadbeef",
+            "DomainUsage": usage,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_accumulates_per_domain_and_user() {
+        let tracker = UsageTracker::new(Duration::from_secs(60));
+        tracker.record("alice", &response(&[("Weather", 1.0), ("Calculator", 0.5)]));
+        tracker.record("bob", &response(&[("Weather", 1.0)]));
+        let snapshot = tracker.snapshot();
+        assert_eq!(snapshot.all_time.queries, 2);
+        assert_eq!(snapshot.all_time.credits, 2.5);
+        assert_eq!(snapshot.all_time.by_domain["Weather"], 2.0);
+        assert_eq!(snapshot.all_time.by_user["alice"], 1.5);
+        assert_eq!(snapshot.window, snapshot.all_time);
+    }
+
+    #[test]
+    fn test_window_and_budget() {
+        let start = Instant::now();
+        let tracker = UsageTracker::new(Duration::from_secs(60)).budget(2.0);
+        tracker.record_at(start, "alice", &response(&[("Weather", 1.5)]));
+        assert!(tracker.check_budget_at(start).is_ok());
+
+        tracker.record_at(start, "alice", &response(&[("Weather", 1.0)]));
+        let err = tracker.check_budget_at(start).unwrap_err();
+        assert_eq!(err.spent(), 2.5);
+
+        let later = start + Duration::from_secs(61);
+        assert!(tracker.check_budget_at(later).is_ok());
+        let snapshot = tracker.snapshot_at(later);
+        assert_eq!(snapshot.window.credits, 0.0);
+        assert_eq!(snapshot.all_time.credits, 2.5);
+    }
+}
@@ -22,11 +22,10 @@ fn test_text_query() {
     let resp = c.text_query(query);
     match resp {
         Ok(r) => {
-            assert_eq!(r.status, "OK");
+            assert_eq!(r.status, Status::Ok);
         },
         Err(e) => {
-            println!("Error={}", e);
-            assert!(false);
+            panic!("Error={}", e);
         }
     }
 }
@@ -40,11 +39,10 @@ fn test_voice_query_success() {
     let resp = c.voice_query(query);
     match resp {
         Ok(r) => {
-            assert_eq!(r.status, "OK");
+            assert_eq!(r.status, Status::Ok);
         },
         Err(e) => {
-            println!("Error={}", e);
-            assert!(false);
+            panic!("Error={}", e);
         },
     }
 }
@@ -58,15 +56,31 @@ fn test_voice_query_unsupported_audio_format() {
     let resp = c.voice_query(query);
     match resp {
         Ok(r) => {
-            assert_eq!(r.status, "Error");
+            assert_eq!(r.status, Status::Error);
             match r.error_message {
                 Some(m) => println!("ErrorMessage={}...", &m[..50]),
                 None => print!("None"),
             }
         },
         Err(e) => {
-            println!("Error={}", e);
-            assert!(false)
+            panic!("Error={}", e);
+        }
+    }
+}
+
+#[cfg(feature = "transcode")]
+#[test]
+fn test_voice_query_from_mp3_file() {
+    let c = get_client();
+    let query =
+        VoiceQuery::from_file("tests/whattimeisit.mp3", "kevinq", RequestInfo::new()).unwrap();
+    let resp = c.voice_query(query);
+    match resp {
+        Ok(r) => {
+            assert_eq!(r.status, Status::Ok);
+        }
+        Err(e) => {
+            panic!("Error={}", e);
         }
     }
 }